@@ -4,6 +4,7 @@ import { invoke } from "@tauri-apps/api/core";
 import { open } from "@tauri-apps/plugin-dialog";
 import { Button } from "@/components/ui/button";
 import {
+  FolderOpen,
   Pause,
   Play,
   SkipBack,
@@ -12,35 +13,65 @@ import {
   StepForward,
 } from "lucide-react";
 import { Seeker } from "@/components/ui/seeker";
-import { useCallback, useEffect, useState } from "react";
+import { MouseEvent, useCallback, useEffect, useState } from "react";
 import { Marquee } from "@/components/ui/marquee";
-import { usePlayerEventBroker } from "@/components/context/PlayerEventBroker";
+import {
+  PlayerError,
+  usePlayerEventBroker,
+} from "@/components/context/PlayerEventBroker";
+
+// Formats supported by libopenmpt, which are commonly found in the wild
+const MODULE_EXTENSIONS = [
+  "mod", "xm", "it", "s3m", "mptm", "mo3", "stm", "669", "amf", "ams",
+  "dbm", "digi", "dmf", "dsm", "far", "gdm", "imf", "j2b", "mdl", "med",
+  "mt2", "mtm", "okt", "plm", "psm", "ptm", "sfx", "stp", "ult", "umx",
+];
+
+function formatError(error: PlayerError): string {
+  return error.path !== null
+    ? `${error.message} (${error.path})`
+    : error.message;
+}
 
 export default function Home() {
   const [title, setTitle] = useState("");
+  const [error, setError] = useState<string | null>(null);
   const broker = usePlayerEventBroker();
 
   useEffect(() => {
+    const updateTitle = (
+      metadata: Array<{ key: string; value: string }>,
+      filename: string
+    ) => {
+      const title = metadata.find((candidate) => candidate.key === "title");
+      const artist = metadata.find((candidate) => candidate.key === "artist");
+
+      if (title) {
+        if (artist) {
+          setTitle(`${artist.value} - ${title.value}`);
+        } else {
+          setTitle(title.value);
+        }
+      } else {
+        setTitle(filename);
+      }
+    };
+
     const id = broker.subscribe((message) => {
       switch (message.event) {
+        case "state":
+          if (message.data.filename !== null) {
+            updateTitle(message.data.metadata, message.data.filename);
+          }
+          break;
         case "loaded":
           console.log(message);
-          const title = message.data.metadata.find(
-            (candidate) => candidate.key === "title"
-          );
-          const artist = message.data.metadata.find(
-            (candidate) => candidate.key === "artist"
-          );
-
-          if (title) {
-            if (artist) {
-              setTitle(`${artist.value} - ${title.value}`);
-            } else {
-              setTitle(title.value);
-            }
-          } else {
-            setTitle(message.data.filename);
-          }
+          setError(null);
+          updateTitle(message.data.metadata, message.data.filename);
+          break;
+        case "error":
+          console.error(message.data);
+          setError(formatError(message.data));
           break;
       }
     });
@@ -50,37 +81,70 @@ export default function Home() {
     };
   }, [broker]);
 
-  const handleLoad = useCallback(async () => {
-    const filepath = await open({
-      multiple: false,
-      directory: true,
-      filters: [],
-    });
-
-    if (filepath !== null) {
-      await invoke("load_module", { filepath });
-    }
+  const handleError = useCallback((error: PlayerError) => {
+    console.error(error);
+    setError(formatError(error));
   }, []);
 
+  // Holding shift appends to the playlist instead of replacing it
+  const load = useCallback(
+    async (paths: string | string[] | null, append: boolean) => {
+      if (paths === null) {
+        return;
+      }
+
+      await invoke("load_module", {
+        paths: Array.isArray(paths) ? paths : [paths],
+        append,
+      }).catch(handleError);
+    },
+    [handleError]
+  );
+
+  const handleLoad = useCallback(
+    async (event: MouseEvent) => {
+      const append = event.shiftKey;
+      const paths = await open({
+        multiple: true,
+        directory: false,
+        filters: [{ name: "Modules", extensions: MODULE_EXTENSIONS }],
+      });
+      await load(paths, append);
+    },
+    [load]
+  );
+
+  const handleLoadFolder = useCallback(
+    async (event: MouseEvent) => {
+      const append = event.shiftKey;
+      const paths = await open({
+        multiple: true,
+        directory: true,
+      });
+      await load(paths, append);
+    },
+    [load]
+  );
+
   const handlePlay = useCallback(async () => {
-    await invoke("play_module");
-  }, []);
+    await invoke("play_module").catch(handleError);
+  }, [handleError]);
 
   const handlePause = useCallback(async () => {
-    await invoke("pause_module");
-  }, []);
+    await invoke("pause_module").catch(handleError);
+  }, [handleError]);
 
   const handleStop = useCallback(async () => {
-    await invoke("stop_module");
-  }, []);
+    await invoke("stop_module").catch(handleError);
+  }, [handleError]);
 
   const handlePrevious = useCallback(async () => {
-    await invoke("previous_module");
-  }, []);
+    await invoke("previous_module").catch(handleError);
+  }, [handleError]);
 
   const handleNext = useCallback(async () => {
-    await invoke("next_module");
-  }, []);
+    await invoke("next_module").catch(handleError);
+  }, [handleError]);
 
   return (
     <main className="max-w-[400px] w-full shadow-lg p-4 rounded-lg mx-auto bg-white">
@@ -89,6 +153,14 @@ export default function Home() {
           <Marquee className="text-lg font-semibold text-zinc-700">
             {title}
           </Marquee>
+          <Button
+            variant="outline"
+            size="icon"
+            className="flex-shrink-0"
+            onClick={handleLoadFolder}
+          >
+            <FolderOpen className="w-5 h-5" />
+          </Button>
           <Button
             variant="outline"
             size="icon"
@@ -100,8 +172,11 @@ export default function Home() {
             <StepForward className="-rotate-90 w-5 h-5" />
           </Button>
         </div>
+        {error !== null && (
+          <p className="mt-1 text-sm text-red-600 truncate">{error}</p>
+        )}
       </div>
-      <Seeker className="mb-4" />
+      <Seeker className="mb-4" onError={handleError} />
       <div className="flex justify-center items-center space-x-2">
         <Button variant="outline" size="icon" onClick={handlePrevious}>
           <SkipBack className="w-5 h-5" />
@@ -11,7 +11,81 @@ import {
   useRef,
 } from "react";
 
+export type PlayerErrorKind =
+  | "io"
+  | "output"
+  | "playback"
+  | "config"
+  | "library"
+  | "unavailable";
+
+export type PlayerError = {
+  kind: PlayerErrorKind;
+  message: string;
+  path: string | null;
+};
+
+export type ShuffleMode = "off" | "random" | "folder" | "weighted";
+
+export type RepeatMode = "off" | "all" | "one" | "stopAfterCurrent";
+
+export type ScanReport = {
+  files: number;
+  directories: number;
+  skippedSymlinks: number;
+  skippedCycles: number;
+  skippedDepth: number;
+  errors: PlayerError[];
+};
+
+export type ScanProgress = {
+  filesSeen: number;
+  modulesFound: number;
+};
+
+export type LibraryStatus = {
+  indexing: boolean;
+  modules: number;
+  checked: number;
+};
+
+export type PlaylistEntry = {
+  path: string;
+  title: string;
+  artist: string | null;
+  format: string | null;
+  tracker: string | null;
+  duration: number | null;
+  resolved: boolean;
+};
+
+export type PlaylistContents = {
+  entries: PlaylistEntry[];
+  currentIndex: number | null;
+};
+
+export type TransportState =
+  | "idle"
+  | "loading"
+  | "playing"
+  | "paused"
+  | "stopped"
+  | "ended";
+
+export type PlayerState = {
+  transport: TransportState;
+  filepath: string | null;
+  filename: string | null;
+  metadata: Array<{ key: string; value: string }>;
+  position: number | null;
+  duration: number | null;
+  playlistIndex: number | null;
+  playlistLength: number;
+  settings: Record<string, unknown>;
+};
+
 export type PlayerEvent =
+  | { event: "state"; data: PlayerState }
   | {
       event: "loaded";
       data: {
@@ -21,11 +95,40 @@ export type PlayerEvent =
         duration: number;
       };
     }
-  | { event: "playing"; data: undefined }
-  | { event: "paused"; data: undefined }
-  | { event: "stopped"; data: undefined }
+  | { event: "stateChanged"; data: { state: TransportState } }
   | { event: "positionUpdated"; data: { position: number; duration: number } }
-  | { event: "seeked"; data: { position: number; duration: number } };
+  | { event: "seeked"; data: { position: number; duration: number } }
+  | {
+      event: "diagnostics";
+      data: {
+        filepath: string;
+        clippedSamples: number;
+        totalSamples: number;
+        peak: number;
+      };
+    }
+  | {
+      event: "deviceChanged";
+      data: { host: string; device: string; fallback: boolean };
+    }
+  | { event: "deviceLost"; data: { device: string; message: string } }
+  | { event: "error"; data: PlayerError }
+  | {
+      event: "playlistChanged";
+      data: { files: string[]; currentIndex: number | null };
+    }
+  | {
+      event: "entriesAppended";
+      data: { index: number; files: string[]; currentIndex: number | null };
+    }
+  | { event: "scanProgress"; data: ScanProgress }
+  | { event: "scanCompleted"; data: ScanReport }
+  | { event: "libraryChanged"; data: LibraryStatus }
+  | { event: "playlistEntryUpdated"; data: PlaylistEntry }
+  | {
+      event: "playbackModesChanged";
+      data: { shuffle: ShuffleMode; repeat: RepeatMode };
+    };
 
 export type PlayerEventsSubscription = (event: PlayerEvent) => void;
 
@@ -6,13 +6,17 @@ import * as SliderPrimitive from "@radix-ui/react-slider";
 import { cn } from "@/lib/utils";
 import { useCallback, useEffect, useRef, useState } from "react";
 import { invoke } from "@tauri-apps/api/core";
-import { usePlayerEventBroker } from "@/components/context/PlayerEventBroker";
+import {
+  PlayerError,
+  usePlayerEventBroker,
+} from "@/components/context/PlayerEventBroker";
 
 export type SeekerProps = {
   className?: string;
+  onError?: (error: PlayerError) => void;
 };
 
-const Seeker = ({ className }: SeekerProps) => {
+const Seeker = ({ className, onError }: SeekerProps) => {
   const [isSeeking, setIsSeeking] = useState(false);
   const [displayedSliderValue, setDisplayedSliderValue] = useState(0);
   const [realPosition, setRealPosition] = useState(0);
@@ -24,6 +28,11 @@ const Seeker = ({ className }: SeekerProps) => {
     isSeekingRef.current = isSeeking;
   }, [isSeeking]);
 
+  const realPositionRef = useRef(realPosition);
+  useEffect(() => {
+    realPositionRef.current = realPosition;
+  }, [realPosition]);
+
   useEffect(() => {
     const id = broker.subscribe((message) => {
       switch (message.event) {
@@ -40,13 +49,17 @@ const Seeker = ({ className }: SeekerProps) => {
           setDuration(message.data.duration);
           setIsSeeking(false);
           break;
-        case "stopped":
-          // @TODO: Something is still wrong here, the slider is not reset to 0
-          // properly
-          setDisplayedSliderValue(0);
-          setRealPosition(0);
-          setDuration(0);
-          setIsSeeking(false);
+        case "stateChanged":
+          switch (message.data.state) {
+            case "stopped":
+            case "ended":
+            case "idle":
+              setDisplayedSliderValue(0);
+              setRealPosition(0);
+              setDuration(0);
+              setIsSeeking(false);
+              break;
+          }
           break;
       }
     });
@@ -61,10 +74,19 @@ const Seeker = ({ className }: SeekerProps) => {
     setDisplayedSliderValue(value);
   }, []);
 
-  const handleOnValueCommit = useCallback(async ([newValue]: [number]) => {
-    await invoke("seek_module", { position: newValue });
-    setDisplayedSliderValue(newValue);
-  }, []);
+  const handleOnValueCommit = useCallback(
+    async ([newValue]: [number]) => {
+      await invoke("seek_module", { position: newValue })
+        .then(() => setDisplayedSliderValue(newValue))
+        .catch((error: PlayerError) => {
+          // Snap back to the actual position, as no seeked event will follow
+          setIsSeeking(false);
+          setDisplayedSliderValue(realPositionRef.current);
+          onError?.(error);
+        });
+    },
+    [onError]
+  );
 
   const formatSeconds = useCallback((seconds: number) => {
     const minutes = Math.floor(seconds / 60);
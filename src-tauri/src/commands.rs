@@ -1,40 +1,260 @@
-use crate::player::{Player, PlayerEvent};
+use crate::dsp::crossfeed::{CrossfeedPreset, CrossfeedSettings};
+use crate::dsp::equalizer::{EqualizerBand, EqualizerPreset, EqualizerSettings};
+use crate::dsp::limiter::LimiterSettings;
+use crate::error::PlayerError;
+use crate::library::{LibraryEntry, LibraryQuery, LibraryStatus};
+use crate::normalization::{NormalizationMode, NormalizationSettings};
+use crate::output::{OutputDevice, OutputSettings};
+use crate::player::{Player, PlayerEvent, PlayerState, PlaylistContents};
+use crate::playlist_file::PlaylistImport;
+use crate::preload::PreloadSettings;
+use crate::scan::ScanOptions;
+use crate::scope::{ScopeFrame, ScopeSettings};
+use crate::shuffle::ShuffleMode;
+use crate::spectrum::{SpectrumFrame, SpectrumSettings};
+use crate::transport::RepeatMode;
+use std::collections::HashMap;
 use std::sync::Mutex;
 use tauri::State;
 
 #[tauri::command]
-pub fn load_module(player: State<Mutex<Player>>, filepath: &str) {
-    player.lock().unwrap().load(filepath);
+pub fn load_module(
+    player: State<Mutex<Player>>,
+    paths: Vec<String>,
+    append: Option<bool>,
+    options: Option<ScanOptions>,
+) -> Result<(), PlayerError> {
+    player
+        .lock()
+        .unwrap()
+        .load(paths, append.unwrap_or(false), options.unwrap_or_default())
+}
+
+#[tauri::command]
+pub fn play_module(player: State<Mutex<Player>>) -> Result<(), PlayerError> {
+    player.lock().unwrap().play()
+}
+
+#[tauri::command]
+pub fn pause_module(player: State<Mutex<Player>>) -> Result<(), PlayerError> {
+    player.lock().unwrap().pause()
+}
+
+#[tauri::command]
+pub fn stop_module(player: State<Mutex<Player>>) -> Result<(), PlayerError> {
+    player.lock().unwrap().stop()
+}
+
+#[tauri::command]
+pub fn next_module(player: State<Mutex<Player>>) -> Result<(), PlayerError> {
+    player.lock().unwrap().next()
+}
+
+#[tauri::command]
+pub fn previous_module(player: State<Mutex<Player>>) -> Result<(), PlayerError> {
+    player.lock().unwrap().previous()
+}
+
+#[tauri::command]
+pub fn seek_module(player: State<Mutex<Player>>, position: f64) -> Result<(), PlayerError> {
+    player.lock().unwrap().seek(position)
+}
+
+#[tauri::command]
+pub fn get_playlist(player: State<Mutex<Player>>) -> Result<PlaylistContents, PlayerError> {
+    player.lock().unwrap().playlist()
+}
+
+#[tauri::command]
+pub fn append_to_playlist(
+    player: State<Mutex<Player>>,
+    paths: Vec<String>,
+    options: Option<ScanOptions>,
+) -> Result<(), PlayerError> {
+    player
+        .lock()
+        .unwrap()
+        .append(paths, options.unwrap_or_default())
+}
+
+#[tauri::command]
+pub fn insert_next(
+    player: State<Mutex<Player>>,
+    paths: Vec<String>,
+    options: Option<ScanOptions>,
+) -> Result<(), PlayerError> {
+    player
+        .lock()
+        .unwrap()
+        .insert_next(paths, options.unwrap_or_default())
+}
+
+#[tauri::command]
+pub fn remove_from_playlist(player: State<Mutex<Player>>, index: usize) -> Result<(), PlayerError> {
+    player.lock().unwrap().remove(index)
+}
+
+#[tauri::command]
+pub fn move_playlist_entry(
+    player: State<Mutex<Player>>,
+    from: usize,
+    to: usize,
+) -> Result<(), PlayerError> {
+    player.lock().unwrap().move_entry(from, to)
+}
+
+#[tauri::command]
+pub fn clear_playlist(player: State<Mutex<Player>>) -> Result<(), PlayerError> {
+    player.lock().unwrap().clear()
+}
+
+#[tauri::command]
+pub fn jump_to_playlist_entry(
+    player: State<Mutex<Player>>,
+    index: usize,
+) -> Result<(), PlayerError> {
+    player.lock().unwrap().jump(index)
+}
+
+#[tauri::command]
+pub fn get_equalizer(player: State<Mutex<Player>>) -> EqualizerSettings {
+    player.lock().unwrap().equalizer().clone()
+}
+
+#[tauri::command]
+pub fn get_equalizer_presets(player: State<Mutex<Player>>) -> Vec<EqualizerPreset> {
+    player.lock().unwrap().equalizer().presets()
 }
 
 #[tauri::command]
-pub fn play_module(player: State<Mutex<Player>>) {
-    player.lock().unwrap().play();
+pub fn set_equalizer_enabled(
+    player: State<Mutex<Player>>,
+    enabled: bool,
+) -> Result<(), PlayerError> {
+    player.lock().unwrap().set_equalizer_enabled(enabled)
+}
+
+#[tauri::command]
+pub fn set_equalizer_band(
+    player: State<Mutex<Player>>,
+    index: usize,
+    band: EqualizerBand,
+) -> Result<bool, PlayerError> {
+    player.lock().unwrap().set_equalizer_band(index, band)
+}
+
+#[tauri::command]
+pub fn set_equalizer_bands(
+    player: State<Mutex<Player>>,
+    bands: Vec<EqualizerBand>,
+) -> Result<(), PlayerError> {
+    player.lock().unwrap().set_equalizer_bands(bands)
+}
+
+#[tauri::command]
+pub fn apply_equalizer_preset(
+    player: State<Mutex<Player>>,
+    name: &str,
+) -> Result<bool, PlayerError> {
+    player.lock().unwrap().apply_equalizer_preset(name)
+}
+
+#[tauri::command]
+pub fn save_equalizer_preset(
+    player: State<Mutex<Player>>,
+    name: &str,
+) -> Result<bool, PlayerError> {
+    player.lock().unwrap().save_equalizer_preset(name)
+}
+
+#[tauri::command]
+pub fn delete_equalizer_preset(
+    player: State<Mutex<Player>>,
+    name: &str,
+) -> Result<bool, PlayerError> {
+    player.lock().unwrap().delete_equalizer_preset(name)
+}
+
+#[tauri::command]
+pub fn get_crossfeed(player: State<Mutex<Player>>) -> CrossfeedSettings {
+    *player.lock().unwrap().crossfeed()
+}
+
+#[tauri::command]
+pub fn set_crossfeed_enabled(
+    player: State<Mutex<Player>>,
+    enabled: bool,
+) -> Result<(), PlayerError> {
+    player.lock().unwrap().set_crossfeed_enabled(enabled)
 }
 
 #[tauri::command]
-pub fn pause_module(player: State<Mutex<Player>>) {
-    player.lock().unwrap().pause();
+pub fn set_crossfeed_preset(
+    player: State<Mutex<Player>>,
+    preset: CrossfeedPreset,
+) -> Result<(), PlayerError> {
+    player.lock().unwrap().set_crossfeed_preset(preset)
 }
 
 #[tauri::command]
-pub fn stop_module(player: State<Mutex<Player>>) {
-    player.lock().unwrap().stop();
+pub fn set_crossfeed_levels(
+    player: State<Mutex<Player>>,
+    cutoff: f64,
+    feed: f64,
+) -> Result<(), PlayerError> {
+    player.lock().unwrap().set_crossfeed_levels(cutoff, feed)
 }
 
 #[tauri::command]
-pub fn next_module(player: State<Mutex<Player>>) {
-    player.lock().unwrap().next();
+pub fn get_normalization(player: State<Mutex<Player>>) -> NormalizationSettings {
+    *player.lock().unwrap().normalization()
 }
 
 #[tauri::command]
-pub fn previous_module(player: State<Mutex<Player>>) {
-    player.lock().unwrap().previous();
+pub fn set_normalization_mode(
+    player: State<Mutex<Player>>,
+    mode: NormalizationMode,
+) -> Result<(), PlayerError> {
+    player.lock().unwrap().set_normalization_mode(mode)
 }
 
 #[tauri::command]
-pub fn seek_module(player: State<Mutex<Player>>, position: f64) {
-    player.lock().unwrap().seek(position);
+pub fn set_normalization_target(
+    player: State<Mutex<Player>>,
+    target: f64,
+) -> Result<(), PlayerError> {
+    player.lock().unwrap().set_normalization_target(target)
+}
+
+#[tauri::command]
+pub fn set_normalization_prevent_clipping(
+    player: State<Mutex<Player>>,
+    prevent_clipping: bool,
+) -> Result<(), PlayerError> {
+    player
+        .lock()
+        .unwrap()
+        .set_normalization_prevent_clipping(prevent_clipping)
+}
+
+#[tauri::command]
+pub fn get_limiter(player: State<Mutex<Player>>) -> LimiterSettings {
+    *player.lock().unwrap().limiter()
+}
+
+#[tauri::command]
+pub fn set_limiter_enabled(player: State<Mutex<Player>>, enabled: bool) -> Result<(), PlayerError> {
+    player.lock().unwrap().set_limiter_enabled(enabled)
+}
+
+#[tauri::command]
+pub fn set_limiter_ceiling(player: State<Mutex<Player>>, ceiling: f64) -> Result<(), PlayerError> {
+    player.lock().unwrap().set_limiter_ceiling(ceiling)
+}
+
+#[tauri::command]
+pub fn get_player_state(player: State<Mutex<Player>>) -> Result<PlayerState, PlayerError> {
+    player.lock().unwrap().state()
 }
 
 #[tauri::command]
@@ -51,3 +271,211 @@ pub fn unsubscribe_from_player_events(player: State<Mutex<Player>>, id: String)
     eprintln!("Unsubscribing from player events with {}", id);
     dbg!(player.lock().unwrap().unsubscribe_from_events(id))
 }
+
+#[tauri::command]
+pub fn get_spectrum_settings(player: State<Mutex<Player>>) -> SpectrumSettings {
+    *player.lock().unwrap().spectrum_settings()
+}
+
+#[tauri::command]
+pub fn set_spectrum_settings(
+    player: State<Mutex<Player>>,
+    rate: u32,
+    bands: usize,
+) -> Result<(), PlayerError> {
+    player
+        .lock()
+        .unwrap()
+        .set_spectrum_settings(SpectrumSettings { rate, bands })
+}
+
+#[tauri::command]
+pub fn subscribe_to_spectrum(
+    player: State<Mutex<Player>>,
+    channel: tauri::ipc::Channel<SpectrumFrame>,
+) -> String {
+    player.lock().unwrap().subscribe_to_spectrum(channel)
+}
+
+#[tauri::command]
+pub fn unsubscribe_from_spectrum(player: State<Mutex<Player>>, id: String) -> bool {
+    player.lock().unwrap().unsubscribe_from_spectrum(id)
+}
+
+#[tauri::command]
+pub fn get_scope_settings(player: State<Mutex<Player>>) -> ScopeSettings {
+    *player.lock().unwrap().scope_settings()
+}
+
+#[tauri::command]
+pub fn set_scope_settings(
+    player: State<Mutex<Player>>,
+    rate: u32,
+    points: usize,
+    channel_scopes: bool,
+) -> Result<(), PlayerError> {
+    player.lock().unwrap().set_scope_settings(ScopeSettings {
+        rate,
+        points,
+        channel_scopes,
+    })
+}
+
+#[tauri::command]
+pub fn subscribe_to_scope(
+    player: State<Mutex<Player>>,
+    channel: tauri::ipc::Channel<ScopeFrame>,
+) -> String {
+    player.lock().unwrap().subscribe_to_scope(channel)
+}
+
+#[tauri::command]
+pub fn unsubscribe_from_scope(player: State<Mutex<Player>>, id: String) -> bool {
+    player.lock().unwrap().unsubscribe_from_scope(id)
+}
+
+#[tauri::command]
+pub fn get_output_hosts(player: State<Mutex<Player>>) -> Vec<String> {
+    player.lock().unwrap().output_hosts()
+}
+
+#[tauri::command]
+pub fn get_output_devices(player: State<Mutex<Player>>, host: Option<String>) -> Vec<OutputDevice> {
+    player.lock().unwrap().output_devices(host.as_deref())
+}
+
+#[tauri::command]
+pub fn get_output_settings(player: State<Mutex<Player>>) -> OutputSettings {
+    player.lock().unwrap().output_settings().clone()
+}
+
+#[tauri::command]
+pub fn select_output_device(
+    player: State<Mutex<Player>>,
+    host: Option<String>,
+    device: Option<String>,
+) -> Result<(), PlayerError> {
+    player.lock().unwrap().select_output_device(host, device)
+}
+
+#[tauri::command]
+pub fn set_pause_on_device_loss(
+    player: State<Mutex<Player>>,
+    enabled: bool,
+) -> Result<(), PlayerError> {
+    player.lock().unwrap().set_pause_on_device_loss(enabled)
+}
+
+#[tauri::command]
+pub fn get_preload_settings(player: State<Mutex<Player>>) -> PreloadSettings {
+    *player.lock().unwrap().preload_settings()
+}
+
+#[tauri::command]
+pub fn set_preload_memory_budget(
+    player: State<Mutex<Player>>,
+    memory_budget: usize,
+) -> Result<(), PlayerError> {
+    player
+        .lock()
+        .unwrap()
+        .set_preload_memory_budget(memory_budget)
+}
+
+#[tauri::command]
+pub fn get_shuffle_mode(player: State<Mutex<Player>>) -> ShuffleMode {
+    player.lock().unwrap().shuffle_mode()
+}
+
+#[tauri::command]
+pub fn set_shuffle_mode(
+    player: State<Mutex<Player>>,
+    mode: ShuffleMode,
+) -> Result<(), PlayerError> {
+    player.lock().unwrap().set_shuffle_mode(mode)
+}
+
+#[tauri::command]
+pub fn get_repeat_mode(player: State<Mutex<Player>>) -> RepeatMode {
+    player.lock().unwrap().repeat_mode()
+}
+
+#[tauri::command]
+pub fn set_repeat_mode(player: State<Mutex<Player>>, mode: RepeatMode) -> Result<(), PlayerError> {
+    player.lock().unwrap().set_repeat_mode(mode)
+}
+
+#[tauri::command]
+pub fn open_playlist(
+    player: State<Mutex<Player>>,
+    filepath: &str,
+) -> Result<PlaylistImport, PlayerError> {
+    player.lock().unwrap().import_playlist(filepath)
+}
+
+#[tauri::command]
+pub fn save_playlist(player: State<Mutex<Player>>, filepath: &str) -> Result<(), PlayerError> {
+    // Other commands must not wait for the modules to be read
+    let export = player.lock().unwrap().export_playlist()?;
+    export.write(filepath)
+}
+
+#[tauri::command]
+pub fn get_library_roots(player: State<Mutex<Player>>) -> Vec<String> {
+    player.lock().unwrap().library_roots()
+}
+
+#[tauri::command]
+pub fn set_library_roots(
+    player: State<Mutex<Player>>,
+    roots: Vec<String>,
+) -> Result<(), PlayerError> {
+    player.lock().unwrap().set_library_roots(roots)
+}
+
+#[tauri::command]
+pub fn update_library(player: State<Mutex<Player>>) -> Result<(), PlayerError> {
+    player.lock().unwrap().update_library()
+}
+
+#[tauri::command]
+pub fn get_library_status(player: State<Mutex<Player>>) -> Result<LibraryStatus, PlayerError> {
+    player.lock().unwrap().library_status()
+}
+
+#[tauri::command]
+pub fn query_library(
+    player: State<Mutex<Player>>,
+    query: Option<LibraryQuery>,
+) -> Result<Vec<LibraryEntry>, PlayerError> {
+    player
+        .lock()
+        .unwrap()
+        .query_library(&query.unwrap_or_default())
+}
+
+#[tauri::command]
+pub fn get_ratings(player: State<Mutex<Player>>) -> Result<HashMap<String, u8>, PlayerError> {
+    player.lock().unwrap().ratings()
+}
+
+#[tauri::command]
+pub fn set_rating(
+    player: State<Mutex<Player>>,
+    path: String,
+    rating: Option<u8>,
+) -> Result<(), PlayerError> {
+    player.lock().unwrap().set_rating(path, rating)
+}
+
+#[tauri::command]
+pub fn load_from_library(
+    player: State<Mutex<Player>>,
+    query: Option<LibraryQuery>,
+    append: Option<bool>,
+) -> Result<usize, PlayerError> {
+    player
+        .lock()
+        .unwrap()
+        .load_from_library(&query.unwrap_or_default(), append.unwrap_or(false))
+}
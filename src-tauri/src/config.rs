@@ -0,0 +1,61 @@
+use crate::dsp::crossfeed::CrossfeedSettings;
+use crate::dsp::equalizer::EqualizerSettings;
+use crate::dsp::limiter::LimiterSettings;
+use crate::library::LibrarySettings;
+use crate::normalization::NormalizationSettings;
+use crate::output::OutputSettings;
+use crate::preload::PreloadSettings;
+use crate::scope::ScopeSettings;
+use crate::shuffle::ShuffleMode;
+use crate::spectrum::SpectrumSettings;
+use crate::transport::RepeatMode;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// User configuration, which is persisted as JSON inside the app config
+/// directory.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Config {
+    #[serde(skip)]
+    path: PathBuf,
+    pub equalizer: EqualizerSettings,
+    pub crossfeed: CrossfeedSettings,
+    pub normalization: NormalizationSettings,
+    pub limiter: LimiterSettings,
+    pub spectrum: SpectrumSettings,
+    pub scope: ScopeSettings,
+    pub output: OutputSettings,
+    pub preload: PreloadSettings,
+    pub shuffle: ShuffleMode,
+    pub repeat: RepeatMode,
+    pub library: LibrarySettings,
+}
+
+impl Config {
+    /// Load the configuration from the given path. A missing or unreadable
+    /// file results in the default configuration.
+    pub fn load(path: &Path) -> Self {
+        let mut config = match std::fs::read(path) {
+            Ok(data) => match serde_json::from_slice::<Config>(&data) {
+                Ok(config) => config,
+                Err(err) => {
+                    eprintln!("Could not parse config {}: {}", path.display(), err);
+                    Config::default()
+                }
+            },
+            Err(_) => Config::default(),
+        };
+        config.path = path.to_path_buf();
+        config
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+}
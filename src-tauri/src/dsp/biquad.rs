@@ -0,0 +1,139 @@
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+#[derive(Eq, PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FilterKind {
+    Peaking,
+    LowShelf,
+    HighShelf,
+    LowPass,
+    HighPass,
+}
+
+/// Normalized biquad coefficients (a0 == 1), calculated according to the
+/// formulas of the "Audio EQ Cookbook" by Robert Bristow-Johnson.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct Coefficients {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+}
+
+impl Coefficients {
+    /// Use an already normalized (a0 == 1) set of coefficients
+    pub fn from_normalized(b0: f64, b1: f64, b2: f64, a1: f64, a2: f64) -> Self {
+        Self { b0, b1, b2, a1, a2 }
+    }
+
+    pub fn new(kind: FilterKind, sample_rate: u32, frequency: f64, q: f64, gain_db: f64) -> Self {
+        let sample_rate = sample_rate as f64;
+        // Keep the center frequency safely below nyquist, as the formulas
+        // break down otherwise.
+        let frequency = frequency.clamp(1.0, sample_rate * 0.49);
+        let q = q.max(0.01);
+
+        let a = 10f64.powf(gain_db / 40.0);
+        let w0 = 2.0 * PI * frequency / sample_rate;
+        let cos_w0 = w0.cos();
+        let alpha = w0.sin() / (2.0 * q);
+        let sqrt_a_alpha = 2.0 * a.sqrt() * alpha;
+
+        let (b0, b1, b2, a0, a1, a2) = match kind {
+            FilterKind::Peaking => (
+                1.0 + alpha * a,
+                -2.0 * cos_w0,
+                1.0 - alpha * a,
+                1.0 + alpha / a,
+                -2.0 * cos_w0,
+                1.0 - alpha / a,
+            ),
+            FilterKind::LowShelf => (
+                a * ((a + 1.0) - (a - 1.0) * cos_w0 + sqrt_a_alpha),
+                2.0 * a * ((a - 1.0) - (a + 1.0) * cos_w0),
+                a * ((a + 1.0) - (a - 1.0) * cos_w0 - sqrt_a_alpha),
+                (a + 1.0) + (a - 1.0) * cos_w0 + sqrt_a_alpha,
+                -2.0 * ((a - 1.0) + (a + 1.0) * cos_w0),
+                (a + 1.0) + (a - 1.0) * cos_w0 - sqrt_a_alpha,
+            ),
+            FilterKind::HighShelf => (
+                a * ((a + 1.0) + (a - 1.0) * cos_w0 + sqrt_a_alpha),
+                -2.0 * a * ((a - 1.0) + (a + 1.0) * cos_w0),
+                a * ((a + 1.0) + (a - 1.0) * cos_w0 - sqrt_a_alpha),
+                (a + 1.0) - (a - 1.0) * cos_w0 + sqrt_a_alpha,
+                2.0 * ((a - 1.0) - (a + 1.0) * cos_w0),
+                (a + 1.0) - (a - 1.0) * cos_w0 - sqrt_a_alpha,
+            ),
+            FilterKind::LowPass => (
+                (1.0 - cos_w0) / 2.0,
+                1.0 - cos_w0,
+                (1.0 - cos_w0) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos_w0,
+                1.0 - alpha,
+            ),
+            FilterKind::HighPass => (
+                (1.0 + cos_w0) / 2.0,
+                -(1.0 + cos_w0),
+                (1.0 + cos_w0) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos_w0,
+                1.0 - alpha,
+            ),
+        };
+
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+}
+
+/// A single biquad filter section for interleaved stereo data, implemented as
+/// transposed direct form II.
+#[derive(Clone, Debug)]
+pub struct Biquad {
+    coefficients: Coefficients,
+    state: [[f64; 2]; 2],
+}
+
+impl Biquad {
+    pub fn new(coefficients: Coefficients) -> Self {
+        Self {
+            coefficients,
+            state: [[0.0; 2]; 2],
+        }
+    }
+
+    /// Replace the coefficients while keeping the filter state, which avoids
+    /// audible clicks if parameters are changed during playback.
+    pub fn set_coefficients(&mut self, coefficients: Coefficients) {
+        self.coefficients = coefficients;
+    }
+
+    pub fn reset(&mut self) {
+        self.state = [[0.0; 2]; 2];
+    }
+
+    #[inline]
+    pub fn process_sample(&mut self, channel: usize, input: f64) -> f64 {
+        let c = &self.coefficients;
+        let z = &mut self.state[channel];
+        let output = c.b0 * input + z[0];
+        z[0] = c.b1 * input - c.a1 * output + z[1];
+        z[1] = c.b2 * input - c.a2 * output;
+        output
+    }
+
+    pub fn process(&mut self, data: &mut [f32]) {
+        for frame in data.chunks_exact_mut(2) {
+            frame[0] = self.process_sample(0, frame[0] as f64) as f32;
+            frame[1] = self.process_sample(1, frame[1] as f64) as f32;
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+pub const MIN_CUTOFF: f64 = 300.0;
+pub const MAX_CUTOFF: f64 = 2_000.0;
+pub const MIN_FEED: f64 = 1.0;
+pub const MAX_FEED: f64 = 15.0;
+
+/// The classic presets shipped with libbs2b
+#[derive(Eq, PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CrossfeedPreset {
+    Default,
+    ChuMoy,
+    JanMeier,
+    Custom,
+}
+
+impl CrossfeedPreset {
+    /// Cutoff frequency in Hz and feed level in dB of the preset
+    pub fn levels(&self) -> Option<(f64, f64)> {
+        match self {
+            CrossfeedPreset::Default => Some((700.0, 4.5)),
+            CrossfeedPreset::ChuMoy => Some((700.0, 6.0)),
+            CrossfeedPreset::JanMeier => Some((650.0, 9.5)),
+            CrossfeedPreset::Custom => None,
+        }
+    }
+}
+
+#[derive(PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CrossfeedSettings {
+    pub enabled: bool,
+    pub preset: CrossfeedPreset,
+    pub cutoff: f64,
+    pub feed: f64,
+}
+
+impl Default for CrossfeedSettings {
+    fn default() -> Self {
+        let (cutoff, feed) = CrossfeedPreset::Default.levels().unwrap();
+        Self {
+            enabled: false,
+            preset: CrossfeedPreset::Default,
+            cutoff,
+            feed,
+        }
+    }
+}
+
+#[derive(Default, Copy, Clone)]
+struct Coefficients {
+    a0_lo: f64,
+    b1_lo: f64,
+    a0_hi: f64,
+    a1_hi: f64,
+    b1_hi: f64,
+    gain: f64,
+}
+
+impl Coefficients {
+    /// Direct port of the filter design used by bs2b: The opposite channel is
+    /// low-passed and mixed in, while the direct channel gets a high shelf to
+    /// compensate for the resulting loss of treble.
+    fn new(sample_rate: u32, cutoff: f64, feed: f64) -> Self {
+        let sample_rate = sample_rate as f64;
+        let gb_lo = feed * -5.0 / 6.0 - 3.0;
+        let gb_hi = feed / 6.0 - 3.0;
+
+        let g_lo = 10f64.powf(gb_lo / 20.0);
+        let g_hi = 1.0 - 10f64.powf(gb_hi / 20.0);
+        let cutoff_hi = cutoff * 2f64.powf((gb_lo - 20.0 * g_hi.log10()) / 12.0);
+
+        let x_lo = (-2.0 * PI * cutoff / sample_rate).exp();
+        let x_hi = (-2.0 * PI * cutoff_hi / sample_rate).exp();
+
+        Self {
+            a0_lo: g_lo * (1.0 - x_lo),
+            b1_lo: x_lo,
+            a0_hi: 1.0 - g_hi * (1.0 - x_hi),
+            a1_hi: -x_hi,
+            b1_hi: x_hi,
+            gain: 1.0 / (1.0 - g_hi + g_lo),
+        }
+    }
+}
+
+/// Headphone crossfeed modelled after the Bauer stereophonic-to-binaural DSP
+/// (bs2b).
+///
+/// It is applied on the already rendered stereo signal and therefore works
+/// on top of whatever stereo separation libopenmpt has been configured with.
+pub struct Crossfeed {
+    settings: CrossfeedSettings,
+    coefficients: Coefficients,
+    sample_rate: Option<u32>,
+    lo: [f64; 2],
+    hi: [f64; 2],
+    last_input: [f64; 2],
+}
+
+impl Crossfeed {
+    pub fn new(settings: CrossfeedSettings) -> Self {
+        Self {
+            settings,
+            coefficients: Coefficients::default(),
+            sample_rate: None,
+            lo: [0.0; 2],
+            hi: [0.0; 2],
+            last_input: [0.0; 2],
+        }
+    }
+
+    pub fn configure(&mut self, settings: CrossfeedSettings) {
+        if !self.settings.enabled && settings.enabled {
+            self.reset();
+        }
+        self.settings = settings;
+
+        if let Some(sample_rate) = self.sample_rate {
+            self.update_coefficients(sample_rate);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.lo = [0.0; 2];
+        self.hi = [0.0; 2];
+        self.last_input = [0.0; 2];
+    }
+
+    fn update_coefficients(&mut self, sample_rate: u32) {
+        self.sample_rate = Some(sample_rate);
+        self.coefficients = Coefficients::new(
+            sample_rate,
+            self.settings.cutoff.clamp(MIN_CUTOFF, MAX_CUTOFF),
+            self.settings.feed.clamp(MIN_FEED, MAX_FEED),
+        );
+    }
+
+    pub fn process(&mut self, sample_rate: u32, data: &mut [f32]) {
+        if !self.settings.enabled {
+            return;
+        }
+
+        if self.sample_rate != Some(sample_rate) {
+            self.update_coefficients(sample_rate);
+        }
+
+        let c = self.coefficients;
+        for frame in data.chunks_exact_mut(2) {
+            let input = [frame[0] as f64, frame[1] as f64];
+
+            for (channel, &sample) in input.iter().enumerate() {
+                self.lo[channel] = c.a0_lo * sample + c.b1_lo * self.lo[channel];
+                self.hi[channel] = c.a0_hi * sample
+                    + c.a1_hi * self.last_input[channel]
+                    + c.b1_hi * self.hi[channel];
+            }
+            self.last_input = input;
+
+            frame[0] = ((self.hi[0] + self.lo[1]) * c.gain) as f32;
+            frame[1] = ((self.hi[1] + self.lo[0]) * c.gain) as f32;
+        }
+    }
+}
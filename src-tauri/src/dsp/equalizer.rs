@@ -0,0 +1,179 @@
+use crate::dsp::biquad::{Biquad, Coefficients, FilterKind};
+use serde::{Deserialize, Serialize};
+
+/// Center frequencies of the default ten band graphic equalizer
+const GRAPHIC_FREQUENCIES: [f64; 10] = [
+    31.25, 62.5, 125.0, 250.0, 500.0, 1_000.0, 2_000.0, 4_000.0, 8_000.0, 16_000.0,
+];
+
+/// Q for peaking bands which are one octave wide
+const GRAPHIC_Q: f64 = 1.41;
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EqualizerBand {
+    pub kind: FilterKind,
+    pub frequency: f64,
+    pub gain: f64,
+    pub q: f64,
+}
+
+impl EqualizerBand {
+    fn coefficients(&self, sample_rate: u32) -> Coefficients {
+        Coefficients::new(self.kind, sample_rate, self.frequency, self.q, self.gain)
+    }
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EqualizerPreset {
+    pub name: String,
+    pub bands: Vec<EqualizerBand>,
+}
+
+impl EqualizerPreset {
+    fn graphic(name: &str, gains: [f64; 10]) -> Self {
+        Self {
+            name: name.to_string(),
+            bands: graphic_bands(gains),
+        }
+    }
+}
+
+fn graphic_bands(gains: [f64; 10]) -> Vec<EqualizerBand> {
+    GRAPHIC_FREQUENCIES
+        .iter()
+        .zip(gains)
+        .map(|(&frequency, gain)| EqualizerBand {
+            kind: FilterKind::Peaking,
+            frequency,
+            gain,
+            q: GRAPHIC_Q,
+        })
+        .collect()
+}
+
+pub fn builtin_presets() -> Vec<EqualizerPreset> {
+    vec![
+        EqualizerPreset::graphic("Flat", [0.0; 10]),
+        EqualizerPreset::graphic(
+            "Warm",
+            [3.0, 3.0, 2.0, 1.0, 0.0, 0.0, -1.0, -2.0, -3.0, -4.0],
+        ),
+        // Roughly models the fixed 4.4kHz RC filter of the Amiga 500 played
+        // back through a small monitor speaker.
+        EqualizerPreset::graphic(
+            "Amiga speaker",
+            [-6.0, -4.0, -2.0, 0.0, 1.0, 1.0, 0.0, -3.0, -8.0, -12.0],
+        ),
+        EqualizerPreset::graphic(
+            "Bass cut",
+            [-8.0, -6.0, -4.0, -2.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+        ),
+        EqualizerPreset::graphic(
+            "Treble cut",
+            [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -1.0, -3.0, -6.0, -9.0],
+        ),
+    ]
+}
+
+/// Persisted equalizer configuration
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct EqualizerSettings {
+    pub enabled: bool,
+    /// Name of the preset the bands were taken from, or `None` if the bands
+    /// have been adjusted manually afterwards.
+    pub preset: Option<String>,
+    pub bands: Vec<EqualizerBand>,
+    pub user_presets: Vec<EqualizerPreset>,
+}
+
+impl Default for EqualizerSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            preset: Some("Flat".to_string()),
+            bands: graphic_bands([0.0; 10]),
+            user_presets: vec![],
+        }
+    }
+}
+
+impl EqualizerSettings {
+    /// All available presets, builtin ones first
+    pub fn presets(&self) -> Vec<EqualizerPreset> {
+        let mut presets = builtin_presets();
+        presets.extend(self.user_presets.iter().cloned());
+        presets
+    }
+
+    pub fn find_preset(&self, name: &str) -> Option<EqualizerPreset> {
+        self.presets()
+            .into_iter()
+            .find(|preset| preset.name == name)
+    }
+}
+
+pub struct Equalizer {
+    enabled: bool,
+    bands: Vec<EqualizerBand>,
+    filters: Vec<Biquad>,
+    sample_rate: Option<u32>,
+}
+
+impl Equalizer {
+    pub fn new(enabled: bool, bands: Vec<EqualizerBand>) -> Self {
+        Self {
+            enabled,
+            bands,
+            filters: vec![],
+            sample_rate: None,
+        }
+    }
+
+    pub fn configure(&mut self, enabled: bool, bands: Vec<EqualizerBand>) {
+        if !self.enabled && enabled {
+            // Don't carry over stale state from the last time the equalizer
+            // was active.
+            self.filters.iter_mut().for_each(Biquad::reset);
+        }
+        self.enabled = enabled;
+        self.bands = bands;
+
+        if let Some(sample_rate) = self.sample_rate {
+            self.update_filters(sample_rate);
+        }
+    }
+
+    fn update_filters(&mut self, sample_rate: u32) {
+        self.sample_rate = Some(sample_rate);
+
+        if self.filters.len() != self.bands.len() {
+            self.filters = self
+                .bands
+                .iter()
+                .map(|band| Biquad::new(band.coefficients(sample_rate)))
+                .collect();
+            return;
+        }
+
+        for (filter, band) in self.filters.iter_mut().zip(&self.bands) {
+            filter.set_coefficients(band.coefficients(sample_rate));
+        }
+    }
+
+    pub fn process(&mut self, sample_rate: u32, data: &mut [f32]) {
+        if !self.enabled {
+            return;
+        }
+
+        if self.sample_rate != Some(sample_rate) {
+            self.update_filters(sample_rate);
+        }
+
+        for filter in self.filters.iter_mut() {
+            filter.process(data);
+        }
+    }
+}
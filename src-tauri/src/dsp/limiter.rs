@@ -0,0 +1,145 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+const LOOKAHEAD_SECONDS: f64 = 0.005;
+const RELEASE_SECONDS: f64 = 0.1;
+
+#[derive(PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LimiterSettings {
+    pub enabled: bool,
+    /// Maximum output level in dBFS
+    pub ceiling: f64,
+}
+
+impl Default for LimiterSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            ceiling: -0.3,
+        }
+    }
+}
+
+/// Counts samples which are outside of the valid -1.0..=1.0 range
+#[derive(Default, Copy, Clone, Debug)]
+pub struct ClippingStats {
+    pub clipped_samples: u64,
+    pub total_samples: u64,
+    pub peak: f32,
+}
+
+impl ClippingStats {
+    pub fn measure(&mut self, data: &[f32]) {
+        for sample in data {
+            let amplitude = sample.abs();
+            if amplitude > 1.0 {
+                self.clipped_samples += 1;
+            }
+            self.peak = self.peak.max(amplitude);
+        }
+        self.total_samples += data.len() as u64;
+    }
+}
+
+/// Look-ahead peak limiter for interleaved stereo data.
+///
+/// The signal is delayed by a few milliseconds, which allows to reduce the
+/// gain smoothly before a peak actually reaches the output.
+pub struct Limiter {
+    settings: LimiterSettings,
+    sample_rate: Option<u32>,
+    lookahead: usize,
+    attack: f32,
+    release: f32,
+    delay: VecDeque<[f32; 2]>,
+    /// Required gains within the look-ahead window, kept as a monotonic queue
+    /// to be able to get the minimum in constant time.
+    window: VecDeque<(usize, f32)>,
+    position: usize,
+    gain: f32,
+}
+
+impl Limiter {
+    pub fn new(settings: LimiterSettings) -> Self {
+        Self {
+            settings,
+            sample_rate: None,
+            lookahead: 0,
+            attack: 1.0,
+            release: 1.0,
+            delay: VecDeque::new(),
+            window: VecDeque::new(),
+            position: 0,
+            gain: 1.0,
+        }
+    }
+
+    pub fn configure(&mut self, settings: LimiterSettings) {
+        if self.settings.enabled != settings.enabled {
+            self.reset();
+        }
+        self.settings = settings;
+    }
+
+    fn reset(&mut self) {
+        self.delay.clear();
+        self.window.clear();
+        self.gain = 1.0;
+    }
+
+    fn prepare(&mut self, sample_rate: u32) {
+        self.sample_rate = Some(sample_rate);
+        self.lookahead = ((sample_rate as f64 * LOOKAHEAD_SECONDS) as usize).max(1);
+        // Reach the required gain within the look-ahead window
+        self.attack = 1.0 - (-4.0 / self.lookahead as f64).exp() as f32;
+        self.release = 1.0 - (-1.0 / (sample_rate as f64 * RELEASE_SECONDS)).exp() as f32;
+        self.reset();
+    }
+
+    pub fn process(&mut self, sample_rate: u32, data: &mut [f32]) {
+        if !self.settings.enabled {
+            return;
+        }
+
+        if self.sample_rate != Some(sample_rate) {
+            self.prepare(sample_rate);
+        }
+
+        let ceiling = 10f64.powf(self.settings.ceiling.min(0.0) / 20.0) as f32;
+        for frame in data.chunks_exact_mut(2) {
+            let peak = frame[0].abs().max(frame[1].abs());
+            let required = if peak > ceiling { ceiling / peak } else { 1.0 };
+
+            while matches!(self.window.back(), Some(&(_, gain)) if gain >= required) {
+                self.window.pop_back();
+            }
+            self.window.push_back((self.position, required));
+            while matches!(self.window.front(), Some(&(position, _)) if position + self.lookahead < self.position)
+            {
+                self.window.pop_front();
+            }
+            self.position += 1;
+
+            let target = self.window.front().map_or(1.0, |&(_, gain)| gain);
+            let coefficient = if target < self.gain {
+                self.attack
+            } else {
+                self.release
+            };
+            self.gain += (target - self.gain) * coefficient;
+
+            self.delay.push_back([frame[0], frame[1]]);
+            let output = if self.delay.len() > self.lookahead {
+                self.delay.pop_front().unwrap()
+            } else {
+                [0.0, 0.0]
+            };
+
+            // The smoothed gain may not have fully settled for very sudden
+            // peaks, so never let anything above the ceiling through.
+            frame[0] = (output[0] * self.gain).clamp(-ceiling, ceiling);
+            frame[1] = (output[1] * self.gain).clamp(-ceiling, ceiling);
+        }
+    }
+}
@@ -0,0 +1,220 @@
+use crate::dsp::biquad::{Biquad, Coefficients};
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+
+/// Lowest level reported for loudness and peaks. This is the absolute gate
+/// defined by EBU R128 and keeps silence from producing `-inf` values.
+pub const MIN_LEVEL: f64 = -70.0;
+
+/// Gating blocks are 400ms long and overlap by 75%, so they are assembled
+/// from four 100ms sub-blocks.
+const SUB_BLOCKS_PER_BLOCK: usize = 4;
+const RELATIVE_GATE: f64 = -10.0;
+
+const OVERSAMPLING: usize = 4;
+const TAPS_PER_PHASE: usize = 12;
+
+fn energy_to_loudness(energy: f64) -> f64 {
+    -0.691 + 10.0 * energy.log10()
+}
+
+pub fn amplitude_to_db(amplitude: f64) -> f64 {
+    (20.0 * amplitude.log10()).max(MIN_LEVEL)
+}
+
+/// The two stage K-weighting filter from ITU-R BS.1770, calculated for an
+/// arbitrary sample rate the same way libebur128 does it.
+fn k_weighting(sample_rate: u32) -> [Biquad; 2] {
+    let sample_rate = sample_rate as f64;
+
+    // Stage 1: High shelf modelling the acoustic effects of the head
+    let f0 = 1681.974450955533;
+    let gain = 3.999843853973347;
+    let q = 0.7071752369554196;
+    let k = (PI * f0 / sample_rate).tan();
+    let vh = 10f64.powf(gain / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Coefficients::from_normalized(
+        (vh + vb * k / q + k * k) / a0,
+        2.0 * (k * k - vh) / a0,
+        (vh - vb * k / q + k * k) / a0,
+        2.0 * (k * k - 1.0) / a0,
+        (1.0 - k / q + k * k) / a0,
+    );
+
+    // Stage 2: RLB high pass
+    let f0 = 38.13547087602444;
+    let q = 0.5003270373238773;
+    let k = (PI * f0 / sample_rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Coefficients::from_normalized(
+        1.0,
+        -2.0,
+        1.0,
+        2.0 * (k * k - 1.0) / a0,
+        (1.0 - k / q + k * k) / a0,
+    );
+
+    [Biquad::new(shelf), Biquad::new(high_pass)]
+}
+
+/// 4x oversampling peak detector using a windowed sinc interpolation filter,
+/// as suggested by ITU-R BS.1770 Annex 2.
+struct TruePeak {
+    phases: [[f64; TAPS_PER_PHASE]; OVERSAMPLING],
+    history: [[f64; TAPS_PER_PHASE]; 2],
+    position: usize,
+    peak: f64,
+}
+
+impl TruePeak {
+    fn new() -> Self {
+        let taps = OVERSAMPLING * TAPS_PER_PHASE;
+        let center = (taps - 1) as f64 / 2.0;
+        let mut phases = [[0.0; TAPS_PER_PHASE]; OVERSAMPLING];
+        for (phase, coefficients) in phases.iter_mut().enumerate() {
+            for (tap, coefficient) in coefficients.iter_mut().enumerate() {
+                let n = tap * OVERSAMPLING + phase;
+                let x = (n as f64 - center) / OVERSAMPLING as f64;
+                let sinc = if x == 0.0 {
+                    1.0
+                } else {
+                    (PI * x).sin() / (PI * x)
+                };
+                let window = 0.42 - 0.5 * (2.0 * PI * n as f64 / (taps - 1) as f64).cos()
+                    + 0.08 * (4.0 * PI * n as f64 / (taps - 1) as f64).cos();
+                *coefficient = sinc * window;
+            }
+
+            // Normalize every phase to unity gain
+            let sum: f64 = coefficients.iter().sum();
+            coefficients.iter_mut().for_each(|c| *c /= sum);
+        }
+
+        Self {
+            phases,
+            history: [[0.0; TAPS_PER_PHASE]; 2],
+            position: 0,
+            peak: 0.0,
+        }
+    }
+
+    fn process(&mut self, frame: [f64; 2]) {
+        self.position = (self.position + 1) % TAPS_PER_PHASE;
+        for (channel, &sample) in frame.iter().enumerate() {
+            self.peak = self.peak.max(sample.abs());
+            self.history[channel][self.position] = sample;
+
+            for coefficients in self.phases.iter() {
+                let mut value = 0.0;
+                for (tap, coefficient) in coefficients.iter().enumerate() {
+                    let index = (self.position + TAPS_PER_PHASE - tap) % TAPS_PER_PHASE;
+                    value += coefficient * self.history[channel][index];
+                }
+                self.peak = self.peak.max(value.abs());
+            }
+        }
+    }
+}
+
+/// Measures integrated loudness and true peak of interleaved stereo data
+/// according to EBU R128 / ITU-R BS.1770.
+pub struct LoudnessMeter {
+    filters: [Biquad; 2],
+    sub_block_frames: usize,
+    sub_block_energy: f64,
+    sub_block_position: usize,
+    sub_blocks: VecDeque<f64>,
+    block_energies: Vec<f64>,
+    true_peak: TruePeak,
+    frames: u64,
+    sample_rate: u32,
+}
+
+impl LoudnessMeter {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            filters: k_weighting(sample_rate),
+            sub_block_frames: sample_rate as usize / 10,
+            sub_block_energy: 0.0,
+            sub_block_position: 0,
+            sub_blocks: VecDeque::with_capacity(SUB_BLOCKS_PER_BLOCK),
+            block_energies: vec![],
+            true_peak: TruePeak::new(),
+            frames: 0,
+            sample_rate,
+        }
+    }
+
+    pub fn process(&mut self, data: &[f32]) {
+        for frame in data.chunks_exact(2) {
+            let frame = [frame[0] as f64, frame[1] as f64];
+            self.true_peak.process(frame);
+
+            for (channel, &sample) in frame.iter().enumerate() {
+                let mut weighted = sample;
+                for filter in self.filters.iter_mut() {
+                    weighted = filter.process_sample(channel, weighted);
+                }
+                self.sub_block_energy += weighted * weighted;
+            }
+
+            self.frames += 1;
+            self.sub_block_position += 1;
+            if self.sub_block_position == self.sub_block_frames {
+                self.finish_sub_block();
+            }
+        }
+    }
+
+    fn finish_sub_block(&mut self) {
+        if self.sub_blocks.len() == SUB_BLOCKS_PER_BLOCK {
+            self.sub_blocks.pop_front();
+        }
+        self.sub_blocks.push_back(self.sub_block_energy);
+        self.sub_block_energy = 0.0;
+        self.sub_block_position = 0;
+
+        if self.sub_blocks.len() == SUB_BLOCKS_PER_BLOCK {
+            let energy = self.sub_blocks.iter().sum::<f64>()
+                / (SUB_BLOCKS_PER_BLOCK * self.sub_block_frames) as f64;
+            self.block_energies.push(energy);
+        }
+    }
+
+    /// Gated integrated loudness in LUFS
+    pub fn integrated_loudness(&self) -> f64 {
+        let absolute_gated: Vec<f64> = self
+            .block_energies
+            .iter()
+            .copied()
+            .filter(|&energy| energy_to_loudness(energy) > MIN_LEVEL)
+            .collect();
+        if absolute_gated.is_empty() {
+            return MIN_LEVEL;
+        }
+
+        let mean = absolute_gated.iter().sum::<f64>() / absolute_gated.len() as f64;
+        let relative_gate = energy_to_loudness(mean) + RELATIVE_GATE;
+        let relative_gated: Vec<f64> = absolute_gated
+            .into_iter()
+            .filter(|&energy| energy_to_loudness(energy) > relative_gate)
+            .collect();
+        if relative_gated.is_empty() {
+            return MIN_LEVEL;
+        }
+
+        let mean = relative_gated.iter().sum::<f64>() / relative_gated.len() as f64;
+        energy_to_loudness(mean).max(MIN_LEVEL)
+    }
+
+    /// Maximum true peak in dBTP
+    pub fn true_peak(&self) -> f64 {
+        amplitude_to_db(self.true_peak.peak)
+    }
+
+    pub fn duration_seconds(&self) -> f64 {
+        self.frames as f64 / self.sample_rate as f64
+    }
+}
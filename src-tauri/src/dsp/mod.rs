@@ -0,0 +1,5 @@
+pub mod biquad;
+pub mod crossfeed;
+pub mod equalizer;
+pub mod limiter;
+pub mod loudness;
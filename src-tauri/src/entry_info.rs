@@ -0,0 +1,210 @@
+use crate::openmpt;
+use crate::openmpt::module::Metadata;
+use crate::player::PlayerEvent;
+use crossbeam::channel::{unbounded, Receiver, Sender};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Information about a playlist entry for display
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct EntryInfo {
+    pub path: String,
+    /// Title of the module, or its filename if it has none
+    pub title: String,
+    pub artist: Option<String>,
+    /// Short format name, like "xm" or "it"
+    pub format: Option<String>,
+    /// Tracker the module has been created with
+    pub tracker: Option<String>,
+    /// Duration in seconds
+    pub duration: Option<f64>,
+    /// False until the metadata of the module has been read
+    pub resolved: bool,
+}
+
+impl EntryInfo {
+    /// Placeholder until the entry has been resolved
+    pub fn unresolved(filepath: &str) -> Self {
+        Self {
+            path: filepath.to_string(),
+            title: filename(filepath),
+            artist: None,
+            format: None,
+            tracker: None,
+            duration: None,
+            resolved: false,
+        }
+    }
+
+    /// Read the metadata of the module at the given path. Files, which can't
+    /// be read, are resolved without any metadata.
+    pub fn read(filepath: &str) -> Self {
+        let mut info = Self::unresolved(filepath);
+        info.resolved = true;
+
+        let module = std::fs::read(filepath)
+            .ok()
+            .and_then(|data| openmpt::module::Module::try_from_memory(&data).ok());
+        let Some(module) = module else {
+            return info;
+        };
+
+        for entry in module.get_metadata().iter() {
+            match entry {
+                Metadata::Title(title) if !title.trim().is_empty() => {
+                    info.title = title.trim().to_string()
+                }
+                Metadata::Artist(artist) => info.artist = non_empty(artist),
+                Metadata::Type(format) => info.format = non_empty(format),
+                Metadata::Tracker(tracker) => info.tracker = non_empty(tracker),
+                _ => {}
+            }
+        }
+        info.duration = Some(module.get_duration_seconds());
+        info
+    }
+}
+
+fn non_empty(value: &str) -> Option<String> {
+    Some(value.trim().to_string()).filter(|value| !value.is_empty())
+}
+
+fn filename(filepath: &str) -> String {
+    Path::new(filepath)
+        .file_name()
+        .map_or(filepath.to_string(), |filename| {
+            filename.to_string_lossy().to_string()
+        })
+}
+
+enum ResolveRequest {
+    /// Resolve these files instead of the previous ones. Entries of other
+    /// files are dropped from the cache.
+    Replace(Vec<String>),
+    /// Resolve these files after the previous ones
+    Append(Vec<String>),
+}
+
+fn resolve_worker(
+    receiver: Receiver<ResolveRequest>,
+    cache: Arc<Mutex<HashMap<String, EntryInfo>>>,
+    event_sender: Sender<PlayerEvent>,
+) {
+    let mut files: Vec<String> = vec![];
+    let mut index = 0;
+    while let Ok(request) = receiver.recv() {
+        let mut request = Some(request);
+        loop {
+            // Only the latest playlist is of interest
+            for request in request.take().into_iter().chain(receiver.try_iter()) {
+                match request {
+                    ResolveRequest::Replace(latest) => {
+                        // Otherwise the cache would grow with every playlist
+                        let kept: HashSet<&String> = latest.iter().collect();
+                        cache
+                            .lock()
+                            .unwrap()
+                            .retain(|filepath, _| kept.contains(filepath));
+                        files = latest;
+                        index = 0;
+                    }
+                    ResolveRequest::Append(mut appended) => files.append(&mut appended),
+                }
+            }
+            if index >= files.len() {
+                break;
+            }
+
+            let filepath = &files[index];
+            index += 1;
+            if cache.lock().unwrap().contains_key(filepath) {
+                continue;
+            }
+
+            let info = EntryInfo::read(filepath);
+            cache.lock().unwrap().insert(filepath.clone(), info.clone());
+            if event_sender
+                .send(PlayerEvent::PlaylistEntryUpdated(info))
+                .is_err()
+            {
+                return;
+            }
+        }
+    }
+}
+
+/// Resolves the metadata of playlist entries on a background thread and
+/// announces every resolved entry with a `PlaylistEntryUpdated` event.
+#[derive(Clone)]
+pub struct EntryResolver {
+    sender: Sender<ResolveRequest>,
+    cache: Arc<Mutex<HashMap<String, EntryInfo>>>,
+}
+
+impl EntryResolver {
+    pub fn spawn(event_sender: Sender<PlayerEvent>) -> Self {
+        let (sender, receiver) = unbounded();
+        let cache = Arc::new(Mutex::new(HashMap::new()));
+        let worker_cache = cache.clone();
+        std::thread::spawn(move || resolve_worker(receiver, worker_cache, event_sender));
+
+        Self { sender, cache }
+    }
+
+    /// Resolve the given files in order, replacing any previous request
+    pub fn resolve(&self, files: Vec<String>) {
+        let _ = self.sender.send(ResolveRequest::Replace(files));
+    }
+
+    /// Resolve the given files after all previously requested ones
+    pub fn append(&self, files: Vec<String>) {
+        let _ = self.sender.send(ResolveRequest::Append(files));
+    }
+
+    /// The known information about the given file, without waiting for it to
+    /// be resolved
+    pub fn get(&self, filepath: &str) -> EntryInfo {
+        self.cache
+            .lock()
+            .unwrap()
+            .get(filepath)
+            .cloned()
+            .unwrap_or_else(|| EntryInfo::unresolved(filepath))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolved(events: &Receiver<PlayerEvent>) -> String {
+        match events.recv().unwrap() {
+            PlayerEvent::PlaylistEntryUpdated(info) => info.path,
+            _ => panic!("unexpected event"),
+        }
+    }
+
+    #[test]
+    fn drops_entries_of_replaced_playlists() {
+        let (sender, events) = unbounded();
+        let resolver = EntryResolver::spawn(sender);
+        resolver.resolve(vec![String::from("/missing/a.mod")]);
+        resolver.append(vec![String::from("/missing/b.mod")]);
+        assert_eq!(resolved(&events), "/missing/a.mod");
+        assert_eq!(resolved(&events), "/missing/b.mod");
+        assert!(resolver.get("/missing/a.mod").resolved);
+
+        // The cached entry isn't announced again, only the new one
+        resolver.resolve(vec![
+            String::from("/missing/b.mod"),
+            String::from("/missing/c.mod"),
+        ]);
+        assert_eq!(resolved(&events), "/missing/c.mod");
+        assert!(!resolver.get("/missing/a.mod").resolved);
+        assert!(resolver.get("/missing/b.mod").resolved);
+        assert_eq!(resolver.cache.lock().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,60 @@
+use serde::Serialize;
+use std::fmt::{Display, Formatter};
+
+#[derive(Serialize, Copy, Clone, Eq, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum ErrorKind {
+    /// A file or directory could not be read
+    Io,
+    /// The output device could not be opened
+    Output,
+    /// The playback thread crashed and has been restarted, or a playback
+    /// command isn't possible right now, like seeking without a module
+    Playback,
+    /// The configuration could not be saved
+    Config,
+    /// The library database could not be read or written
+    Library,
+    /// The playback thread is not running
+    Unavailable,
+}
+
+/// Error reported to the frontend, either as result of a command or as
+/// `PlayerEvent::Error`.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerError {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub path: Option<String>,
+}
+
+impl PlayerError {
+    pub fn new(kind: ErrorKind, message: impl Display) -> Self {
+        Self {
+            kind,
+            message: message.to_string(),
+            path: None,
+        }
+    }
+
+    pub fn with_path(mut self, path: impl Into<String>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    pub fn unavailable() -> Self {
+        Self::new(ErrorKind::Unavailable, "Playback thread is not running")
+    }
+}
+
+impl Display for PlayerError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.path {
+            Some(ref path) => write!(f, "{}: {}", path, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for PlayerError {}
@@ -0,0 +1,125 @@
+use crate::player::PlayerEvent;
+use crossbeam::channel::{unbounded, Receiver, Sender};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+/// Events, which are only relevant until the next one of the same kind
+/// arrives. They are dropped for subscribers, which can't keep up.
+fn is_coalescable(event: &PlayerEvent) -> bool {
+    matches!(event, PlayerEvent::PositionUpdated { .. })
+}
+
+/// Queue of a single event subscriber.
+///
+/// Every subscriber is served by its own delivery thread, so a slow channel
+/// never stalls playback or other subscribers.
+pub struct EventSubscriber {
+    sender: Sender<PlayerEvent>,
+}
+
+impl EventSubscriber {
+    pub fn spawn(id: String, channel: tauri::ipc::Channel<PlayerEvent>) -> Self {
+        let (sender, receiver) = unbounded();
+        std::thread::spawn(move || deliver(id, channel, receiver));
+        Self { sender }
+    }
+
+    /// Queue an event without blocking. Returns false, if the subscriber is
+    /// gone.
+    pub fn push(&self, event: PlayerEvent) -> bool {
+        self.sender.send(event).is_ok()
+    }
+}
+
+fn deliver(id: String, channel: tauri::ipc::Channel<PlayerEvent>, receiver: Receiver<PlayerEvent>) {
+    while let Ok(event) = receiver.recv() {
+        let mut batch = vec![event];
+        batch.extend(receiver.try_iter());
+
+        // Of a backlog only the latest coalescable event is delivered
+        let latest = batch.iter().rposition(is_coalescable);
+        for (index, event) in batch.into_iter().enumerate() {
+            if is_coalescable(&event) && Some(index) != latest {
+                continue;
+            }
+
+            if let Err(err) = channel.send(event) {
+                eprintln!("Dropping event subscriber {}: {}", id, err);
+                return;
+            }
+        }
+    }
+}
+
+const MIN_FRAME_RATE: u32 = 1;
+const MAX_FRAME_RATE: u32 = 60;
+
+/// Limit a requested number of broadcast frames per second to a range the
+/// frontend can keep up with
+pub fn clamp_frame_rate(rate: u32) -> u32 {
+    rate.clamp(MIN_FRAME_RATE, MAX_FRAME_RATE)
+}
+
+/// Fan-out of frames to subscribed channels, shared by the visualization
+/// taps. Clones publish to the same subscribers.
+///
+/// The active flag is cleared while nobody is subscribed, so the audio side
+/// can skip collecting data nobody is going to see.
+#[derive(Clone)]
+pub struct Broadcast<T: Serialize + Clone> {
+    name: &'static str,
+    subscribers: Arc<Mutex<HashMap<String, tauri::ipc::Channel<T>>>>,
+    active: Arc<AtomicBool>,
+}
+
+impl<T: Serialize + Clone> Broadcast<T> {
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            subscribers: Arc::new(Mutex::new(HashMap::new())),
+            active: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Flag, which is set as long as there are subscribers
+    pub fn active(&self) -> Arc<AtomicBool> {
+        self.active.clone()
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    pub fn subscribe(&self, channel: tauri::ipc::Channel<T>) -> String {
+        let uuid = Uuid::new_v4().to_string();
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.insert(uuid.clone(), channel);
+        self.active.store(true, Ordering::Relaxed);
+        uuid
+    }
+
+    pub fn unsubscribe(&self, uuid: String) -> bool {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        let removed = subscribers.remove(&uuid).is_some();
+        self.active
+            .store(!subscribers.is_empty(), Ordering::Relaxed);
+        removed
+    }
+
+    /// Send a frame to every subscriber, dropping those whose channel failed
+    pub fn publish(&self, frame: T) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|id, subscriber| match subscriber.send(frame.clone()) {
+            Ok(_) => true,
+            Err(err) => {
+                eprintln!("Dropping {} subscriber {}: {}", self.name, id, err);
+                false
+            }
+        });
+        self.active
+            .store(!subscribers.is_empty(), Ordering::Relaxed);
+    }
+}
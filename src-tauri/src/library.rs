@@ -0,0 +1,1098 @@
+use crate::error::{ErrorKind, PlayerError};
+use crate::openmpt;
+use crate::openmpt::module::Metadata;
+use crate::player::PlayerEvent;
+use crate::scan::{natural_cmp, ScanJob, ScanMessage, ScanOptions};
+use anyhow::Result;
+use crossbeam::channel::{unbounded, Receiver, Sender};
+use rusqlite::types::ToSql;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::UNIX_EPOCH;
+
+/// Number of checked files after which the indexing status is reported
+const STATUS_INTERVAL: usize = 100;
+
+/// Ratings range from 1 to this many stars
+pub const MAX_RATING: u8 = 5;
+
+const SCHEMA: &str = "
+    PRAGMA journal_mode = WAL;
+    PRAGMA synchronous = NORMAL;
+    CREATE TABLE IF NOT EXISTS modules (
+        path TEXT PRIMARY KEY NOT NULL,
+        root TEXT NOT NULL,
+        hash TEXT NOT NULL,
+        size INTEGER NOT NULL,
+        modified INTEGER NOT NULL,
+        format TEXT,
+        format_long TEXT,
+        original_format TEXT,
+        original_format_long TEXT,
+        container TEXT,
+        container_long TEXT,
+        tracker TEXT,
+        artist TEXT,
+        title TEXT,
+        date TEXT,
+        message TEXT,
+        message_raw TEXT,
+        warnings TEXT,
+        duration REAL NOT NULL,
+        channels INTEGER NOT NULL,
+        subsongs INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS modules_root ON modules (root);
+    CREATE INDEX IF NOT EXISTS modules_hash ON modules (hash);
+    CREATE TABLE IF NOT EXISTS unplayable (
+        path TEXT PRIMARY KEY NOT NULL,
+        root TEXT NOT NULL,
+        size INTEGER NOT NULL,
+        modified INTEGER NOT NULL
+    );
+    -- Apart from the modules, so ratings survive changes and re-indexing
+    CREATE TABLE IF NOT EXISTS ratings (
+        path TEXT PRIMARY KEY NOT NULL,
+        rating INTEGER NOT NULL
+    );
+";
+
+/// Columns of the modules table in the order of `LibraryEntry::from_row`
+const COLUMNS: &str = "path, hash, size, modified, format, format_long, original_format, \
+    original_format_long, container, container_long, tracker, artist, title, date, message, \
+    message_raw, warnings, duration, channels, subsongs";
+
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LibrarySettings {
+    /// Folders, whose modules are indexed
+    pub roots: Vec<String>,
+}
+
+/// An indexed module with all of its metadata
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryEntry {
+    pub path: String,
+    /// SHA-256 of the file contents
+    pub hash: String,
+    pub size: i64,
+    /// Modification time in seconds since the epoch
+    pub modified: i64,
+    /// Short format name, like "xm" or "it"
+    pub format: Option<String>,
+    pub format_long: Option<String>,
+    pub original_format: Option<String>,
+    pub original_format_long: Option<String>,
+    pub container: Option<String>,
+    pub container_long: Option<String>,
+    /// Tracker the module has been created with
+    pub tracker: Option<String>,
+    pub artist: Option<String>,
+    pub title: Option<String>,
+    pub date: Option<String>,
+    pub message: Option<String>,
+    pub message_raw: Option<String>,
+    pub warnings: Option<String>,
+    /// Duration in seconds
+    pub duration: f64,
+    pub channels: i32,
+    pub subsongs: i32,
+}
+
+impl LibraryEntry {
+    fn read(path: &str, data: &[u8], hash: String, size: i64, modified: i64) -> Result<Self> {
+        let module = openmpt::module::Module::try_from_memory(data)?;
+        let mut entry = Self {
+            path: path.to_string(),
+            hash,
+            size,
+            modified,
+            format: None,
+            format_long: None,
+            original_format: None,
+            original_format_long: None,
+            container: None,
+            container_long: None,
+            tracker: None,
+            artist: None,
+            title: None,
+            date: None,
+            message: None,
+            message_raw: None,
+            warnings: None,
+            duration: module.get_duration_seconds(),
+            channels: module.get_num_channels(),
+            subsongs: module.get_num_subsongs(),
+        };
+
+        for metadata in module.get_metadata().iter().cloned() {
+            match metadata {
+                Metadata::Type(value) => entry.format = Some(value),
+                Metadata::TypeLong(value) => entry.format_long = Some(value),
+                Metadata::OriginalType(value) => entry.original_format = Some(value),
+                Metadata::OriginalTypeLong(value) => entry.original_format_long = Some(value),
+                Metadata::Container(value) => entry.container = Some(value),
+                Metadata::ContainerLong(value) => entry.container_long = Some(value),
+                Metadata::Tracker(value) => entry.tracker = Some(value),
+                Metadata::Artist(value) => entry.artist = Some(value),
+                Metadata::Title(value) => entry.title = Some(value),
+                Metadata::Date(value) => entry.date = Some(value),
+                Metadata::Message(value) => entry.message = Some(value),
+                Metadata::MessageRaw(value) => entry.message_raw = Some(value),
+                Metadata::Warnings(value) => entry.warnings = Some(value),
+            }
+        }
+        Ok(entry)
+    }
+
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            path: row.get(0)?,
+            hash: row.get(1)?,
+            size: row.get(2)?,
+            modified: row.get(3)?,
+            format: row.get(4)?,
+            format_long: row.get(5)?,
+            original_format: row.get(6)?,
+            original_format_long: row.get(7)?,
+            container: row.get(8)?,
+            container_long: row.get(9)?,
+            tracker: row.get(10)?,
+            artist: row.get(11)?,
+            title: row.get(12)?,
+            date: row.get(13)?,
+            message: row.get(14)?,
+            message_raw: row.get(15)?,
+            warnings: row.get(16)?,
+            duration: row.get(17)?,
+            channels: row.get(18)?,
+            subsongs: row.get(19)?,
+        })
+    }
+
+    fn insert(&self, connection: &Connection, root: &str) -> rusqlite::Result<()> {
+        connection.execute(
+            &format!(
+                "INSERT OR REPLACE INTO modules (root, {}) VALUES \
+                 (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, \
+                 ?18, ?19, ?20, ?21)",
+                COLUMNS
+            ),
+            params![
+                root,
+                self.path,
+                self.hash,
+                self.size,
+                self.modified,
+                self.format,
+                self.format_long,
+                self.original_format,
+                self.original_format_long,
+                self.container,
+                self.container_long,
+                self.tracker,
+                self.artist,
+                self.title,
+                self.date,
+                self.message,
+                self.message_raw,
+                self.warnings,
+                self.duration,
+                self.channels,
+                self.subsongs,
+            ],
+        )?;
+        Ok(())
+    }
+}
+
+#[derive(Eq, PartialEq, Copy, Clone, Default, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LibrarySort {
+    /// Natural order of the paths, like a folder scan
+    #[default]
+    Path,
+    Title,
+    Artist,
+    Format,
+    Duration,
+    Modified,
+}
+
+/// Filter and order of a library query. All given filters have to match.
+#[derive(Clone, Default, Debug, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LibraryQuery {
+    /// Words, which all have to appear in the path, title, artist, tracker
+    /// or message
+    pub search: Option<String>,
+    pub artist: Option<String>,
+    /// Short format name, like "xm" or "it"
+    pub format: Option<String>,
+    pub tracker: Option<String>,
+    /// Only modules below this folder
+    pub folder: Option<String>,
+    /// Minimum duration in seconds
+    pub min_duration: Option<f64>,
+    /// Maximum duration in seconds
+    pub max_duration: Option<f64>,
+    pub sort: LibrarySort,
+    pub descending: bool,
+    pub limit: Option<usize>,
+    pub offset: usize,
+}
+
+impl LibraryQuery {
+    fn to_sql(&self) -> (String, Vec<Box<dyn ToSql>>) {
+        let mut conditions: Vec<&str> = vec![];
+        let mut params: Vec<Box<dyn ToSql>> = vec![];
+
+        if let Some(ref search) = self.search {
+            for word in search.split_whitespace() {
+                conditions.push(
+                    "instr(lower(path || ' ' || ifnull(title, '') || ' ' || ifnull(artist, '') \
+                     || ' ' || ifnull(tracker, '') || ' ' || ifnull(message, '')), lower(?)) > 0",
+                );
+                params.push(Box::new(word.to_string()));
+            }
+        }
+        for (column, value) in [
+            ("artist = ? COLLATE NOCASE", &self.artist),
+            ("format = ? COLLATE NOCASE", &self.format),
+            ("tracker = ? COLLATE NOCASE", &self.tracker),
+        ] {
+            if let Some(value) = value {
+                conditions.push(column);
+                params.push(Box::new(value.clone()));
+            }
+        }
+        if let Some(ref folder) = self.folder {
+            let prefix = Path::new(folder).join("").to_string_lossy().to_string();
+            conditions.push("instr(path, ?) = 1");
+            params.push(Box::new(prefix));
+        }
+        if let Some(min) = self.min_duration {
+            conditions.push("duration >= ?");
+            params.push(Box::new(min));
+        }
+        if let Some(max) = self.max_duration {
+            conditions.push("duration <= ?");
+            params.push(Box::new(max));
+        }
+
+        let mut sql = format!("SELECT {} FROM modules", COLUMNS);
+        if !conditions.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&conditions.join(" AND "));
+        }
+
+        let order = match self.sort {
+            LibrarySort::Path => "path COLLATE natural_order",
+            LibrarySort::Title => "ifnull(title, path) COLLATE NOCASE",
+            LibrarySort::Artist => "artist COLLATE NOCASE",
+            LibrarySort::Format => "format COLLATE NOCASE",
+            LibrarySort::Duration => "duration",
+            LibrarySort::Modified => "modified",
+        };
+        let direction = if self.descending { "DESC" } else { "ASC" };
+        sql.push_str(&format!(
+            " ORDER BY {} {}, path COLLATE natural_order {}",
+            order, direction, direction
+        ));
+
+        // SQLite treats a negative limit as no limit
+        sql.push_str(" LIMIT ? OFFSET ?");
+        params.push(Box::new(self.limit.map_or(-1, |limit| limit as i64)));
+        params.push(Box::new(self.offset as i64));
+
+        (sql, params)
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryStatus {
+    /// Whether the roots are being indexed right now
+    pub indexing: bool,
+    /// Number of indexed modules
+    pub modules: usize,
+    /// Files checked by the running or last indexing run
+    pub checked: usize,
+}
+
+fn prepare_database(connection: &Connection) -> rusqlite::Result<()> {
+    connection.execute_batch(SCHEMA)?;
+    // "natural" alone is a keyword, which SQLite doesn't accept as collation
+    connection.create_collation("natural_order", natural_cmp)
+}
+
+fn open_database(path: &Path) -> Result<Connection> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let connection = Connection::open(path)?;
+    prepare_database(&connection)?;
+    Ok(connection)
+}
+
+fn open_temporary_database() -> rusqlite::Result<Connection> {
+    let connection = Connection::open_in_memory()?;
+    prepare_database(&connection)?;
+    Ok(connection)
+}
+
+fn count_modules(connection: &Connection) -> usize {
+    connection
+        .query_row("SELECT count(*) FROM modules", [], |row| {
+            row.get::<_, i64>(0)
+        })
+        .map_or(0, |count| count as usize)
+}
+
+fn modification_time(metadata: &std::fs::Metadata) -> i64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |duration| duration.as_secs() as i64)
+}
+
+/// Result of checking a single file against the library
+enum Checked {
+    Unchanged,
+    Changed(Box<LibraryEntry>),
+    /// Not a module libopenmpt is able to load. It isn't read again, as long
+    /// as its size and modification time stay the same.
+    Unplayable {
+        size: i64,
+        modified: i64,
+    },
+}
+
+/// A file, which could not be loaded as module
+struct UnplayableFile {
+    path: String,
+    size: i64,
+    modified: i64,
+}
+
+/// Checked files of a scan batch, which are written at once
+#[derive(Default)]
+struct Batch {
+    unchanged: Vec<String>,
+    changed: Vec<LibraryEntry>,
+    unplayable: Vec<UnplayableFile>,
+}
+
+struct Indexer {
+    receiver: Receiver<Vec<String>>,
+    connection: Arc<Mutex<Connection>>,
+    status: Arc<Mutex<LibraryStatus>>,
+    event_sender: Sender<PlayerEvent>,
+}
+
+impl Indexer {
+    fn run(self) {
+        while let Ok(mut roots) = self.receiver.recv() {
+            // Start over, whenever the roots change during an update
+            while let Some(latest) = self.update(&roots) {
+                roots = latest;
+            }
+        }
+    }
+
+    fn set_status(&self, change: impl FnOnce(&mut LibraryStatus)) {
+        let mut status = self.status.lock().unwrap();
+        change(&mut status);
+        status.modules = count_modules(&self.connection.lock().unwrap());
+        let _ = self.event_sender.send(PlayerEvent::LibraryChanged(*status));
+    }
+
+    fn report(&self, error: PlayerError) {
+        eprintln!("Library error: {}", error);
+        let _ = self.event_sender.send(PlayerEvent::from(error));
+    }
+
+    /// Index all roots and forget about modules, which are gone. Returns the
+    /// new roots, if another update has been requested in between.
+    fn update(&self, roots: &[String]) -> Option<Vec<String>> {
+        self.set_status(|status| {
+            status.indexing = true;
+            status.checked = 0;
+        });
+
+        let options = ScanOptions::default();
+        let mut unplayable = 0;
+        for root in roots {
+            // An unmounted drive must not wipe its modules from the library
+            if !Path::new(root).is_dir() {
+                self.report(
+                    PlayerError::new(ErrorKind::Io, "Library folder is not available")
+                        .with_path(root),
+                );
+                continue;
+            }
+
+            let job = ScanJob::spawn(vec![root.clone()], options.clone());
+            let mut seen = HashSet::new();
+            while let Some(message) = job.recv() {
+                match message {
+                    ScanMessage::Files(files) => {
+                        let mut batch = Batch::default();
+                        let mut interrupted = None;
+                        for filepath in files {
+                            if let Some(latest) = self.receiver.try_iter().last() {
+                                interrupted = Some(latest);
+                                break;
+                            }
+                            match self.check(&filepath) {
+                                Some(Checked::Unchanged) => batch.unchanged.push(filepath.clone()),
+                                Some(Checked::Changed(entry)) => batch.changed.push(*entry),
+                                Some(Checked::Unplayable { size, modified }) => {
+                                    unplayable += 1;
+                                    batch.unplayable.push(UnplayableFile {
+                                        path: filepath.clone(),
+                                        size,
+                                        modified,
+                                    });
+                                }
+                                None => {}
+                            }
+                            seen.insert(filepath);
+                        }
+
+                        if let Err(err) = self.write(root, &batch) {
+                            self.report(PlayerError::new(ErrorKind::Library, err));
+                        }
+                        if interrupted.is_some() {
+                            return interrupted;
+                        }
+                    }
+                    ScanMessage::Progress(_) => {}
+                    ScanMessage::Finished(report) => {
+                        for error in report.errors {
+                            self.report(error);
+                        }
+                        if let Err(err) = self.remove_missing(root, &seen) {
+                            self.report(PlayerError::new(ErrorKind::Library, err));
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Err(err) = self.remove_roots(roots) {
+            self.report(PlayerError::new(ErrorKind::Library, err));
+        }
+        // Collections often contain broken or unsupported files, which are
+        // summarized instead of reporting every single one
+        if unplayable > 0 {
+            self.report(PlayerError::new(
+                ErrorKind::Io,
+                format!("{} files could not be loaded as modules", unplayable),
+            ));
+        }
+        self.set_status(|status| status.indexing = false);
+        None
+    }
+
+    fn check(&self, filepath: &str) -> Option<Checked> {
+        let checked = match self.index(filepath) {
+            Ok(checked) => Some(checked),
+            Err(err) => {
+                let kind = match err.is::<rusqlite::Error>() {
+                    true => ErrorKind::Library,
+                    false => ErrorKind::Io,
+                };
+                self.report(PlayerError::new(kind, err).with_path(filepath));
+                None
+            }
+        };
+
+        let count = {
+            let mut status = self.status.lock().unwrap();
+            status.checked += 1;
+            status.checked
+        };
+        if count % STATUS_INTERVAL == 0 {
+            self.set_status(|_| {});
+        }
+        checked
+    }
+
+    /// Read a single module, unless it is unchanged. Moved or copied modules
+    /// are recognized by their hash.
+    fn index(&self, filepath: &str) -> Result<Checked> {
+        let metadata = std::fs::metadata(filepath)?;
+        let size = metadata.len() as i64;
+        let modified = modification_time(&metadata);
+
+        let (known, known_unplayable) = {
+            let connection = self.connection.lock().unwrap();
+            let query = |table: &str| -> rusqlite::Result<Option<(i64, i64)>> {
+                connection
+                    .query_row(
+                        &format!("SELECT size, modified FROM {} WHERE path = ?1", table),
+                        [filepath],
+                        |row| Ok((row.get(0)?, row.get(1)?)),
+                    )
+                    .optional()
+            };
+            (query("modules")?, query("unplayable")?)
+        };
+        if known == Some((size, modified)) {
+            return Ok(Checked::Unchanged);
+        }
+        if known_unplayable == Some((size, modified)) {
+            return Ok(Checked::Unplayable { size, modified });
+        }
+
+        let data = std::fs::read(filepath)?;
+        let hash = format!("{:x}", Sha256::digest(&data));
+        let duplicate = self
+            .connection
+            .lock()
+            .unwrap()
+            .query_row(
+                &format!("SELECT {} FROM modules WHERE hash = ?1 LIMIT 1", COLUMNS),
+                [&hash],
+                LibraryEntry::from_row,
+            )
+            .optional()?;
+        let entry = match duplicate {
+            Some(duplicate) => LibraryEntry {
+                path: filepath.to_string(),
+                size,
+                modified,
+                ..duplicate
+            },
+            None => match LibraryEntry::read(filepath, &data, hash, size, modified) {
+                Ok(entry) => entry,
+                Err(err) => {
+                    eprintln!("Could not load {}: {}", filepath, err);
+                    return Ok(Checked::Unplayable { size, modified });
+                }
+            },
+        };
+        Ok(Checked::Changed(Box::new(entry)))
+    }
+
+    /// Store the checked files of a batch in a single transaction
+    fn write(&self, root: &str, batch: &Batch) -> rusqlite::Result<()> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        for filepath in batch.unchanged.iter() {
+            transaction.execute(
+                "UPDATE modules SET root = ?1 WHERE path = ?2",
+                [root, filepath],
+            )?;
+        }
+        for entry in batch.changed.iter() {
+            transaction.execute("DELETE FROM unplayable WHERE path = ?1", [&entry.path])?;
+            entry.insert(&transaction, root)?;
+        }
+        for file in batch.unplayable.iter() {
+            transaction.execute("DELETE FROM modules WHERE path = ?1", [&file.path])?;
+            transaction.execute(
+                "INSERT OR REPLACE INTO unplayable (path, root, size, modified) \
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![file.path, root, file.size, file.modified],
+            )?;
+        }
+        transaction.commit()
+    }
+
+    fn remove_missing(&self, root: &str, seen: &HashSet<String>) -> rusqlite::Result<()> {
+        let connection = self.connection.lock().unwrap();
+        for table in ["modules", "unplayable"] {
+            let known = connection
+                .prepare(&format!("SELECT path FROM {} WHERE root = ?1", table))?
+                .query_map([root], |row| row.get::<_, String>(0))?
+                .collect::<rusqlite::Result<Vec<String>>>()?;
+            for path in known.iter().filter(|path| !seen.contains(*path)) {
+                connection.execute(&format!("DELETE FROM {} WHERE path = ?1", table), [path])?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Forget the modules of roots, which have been removed
+    fn remove_roots(&self, roots: &[String]) -> rusqlite::Result<()> {
+        let connection = self.connection.lock().unwrap();
+        for table in ["modules", "unplayable"] {
+            let known = connection
+                .prepare(&format!("SELECT DISTINCT root FROM {}", table))?
+                .query_map([], |row| row.get::<_, String>(0))?
+                .collect::<rusqlite::Result<Vec<String>>>()?;
+            for root in known.iter().filter(|root| !roots.contains(root)) {
+                connection.execute(&format!("DELETE FROM {} WHERE root = ?1", table), [root])?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Index of all modules below the configured root folders, persisted in an
+/// SQLite database. Roots are indexed on a background thread, only new and
+/// changed files are read.
+pub struct Library {
+    sender: Sender<Vec<String>>,
+    connection: Arc<Mutex<Connection>>,
+    status: Arc<Mutex<LibraryStatus>>,
+}
+
+impl Library {
+    /// Open the library database. If it can't be opened, a temporary one is
+    /// used for this session.
+    pub fn spawn(
+        database_path: &Path,
+        event_sender: Sender<PlayerEvent>,
+    ) -> Result<Self, PlayerError> {
+        let connection = match open_database(database_path) {
+            Ok(connection) => connection,
+            Err(err) => {
+                eprintln!(
+                    "Could not open library {}, using a temporary one: {}",
+                    database_path.display(),
+                    err
+                );
+                open_temporary_database()
+                    .map_err(|err| PlayerError::new(ErrorKind::Library, err))?
+            }
+        };
+
+        let (sender, receiver) = unbounded();
+        let connection = Arc::new(Mutex::new(connection));
+        let status = Arc::new(Mutex::new(LibraryStatus::default()));
+        let indexer = Indexer {
+            receiver,
+            connection: connection.clone(),
+            status: status.clone(),
+            event_sender,
+        };
+        std::thread::spawn(move || indexer.run());
+
+        Ok(Self {
+            sender,
+            connection,
+            status,
+        })
+    }
+
+    /// Index the given roots, replacing any previous roots
+    pub fn update(&self, roots: Vec<String>) {
+        if self.sender.send(roots).is_err() {
+            eprintln!("Library indexer is not running");
+        }
+    }
+
+    pub fn status(&self) -> LibraryStatus {
+        let mut status = *self.status.lock().unwrap();
+        status.modules = count_modules(&self.connection.lock().unwrap());
+        status
+    }
+
+    pub fn query(&self, query: &LibraryQuery) -> Result<Vec<LibraryEntry>> {
+        let (sql, params) = query.to_sql();
+        let connection = self.connection.lock().unwrap();
+        let entries = connection
+            .prepare(&sql)?
+            .query_map(
+                rusqlite::params_from_iter(params.iter().map(|param| param.as_ref())),
+                LibraryEntry::from_row,
+            )?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(entries)
+    }
+
+    /// Ratings of all rated modules by path
+    pub fn ratings(&self) -> Result<HashMap<String, u8>> {
+        let connection = self.connection.lock().unwrap();
+        let ratings = connection
+            .prepare("SELECT path, rating FROM ratings")?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<HashMap<_, _>>>()?;
+        Ok(ratings)
+    }
+
+    /// Rate a module from 1 to `MAX_RATING`, `None` removes its rating
+    pub fn set_rating(&self, path: &str, rating: Option<u8>) -> Result<()> {
+        let connection = self.connection.lock().unwrap();
+        match rating {
+            Some(rating) => connection.execute(
+                "INSERT OR REPLACE INTO ratings (path, rating) VALUES (?1, ?2)",
+                params![path, rating],
+            )?,
+            None => connection.execute("DELETE FROM ratings WHERE path = ?1", [path])?,
+        };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const MODULE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../assets/hippos.mod");
+
+    fn entry(path: &str, title: Option<&str>, artist: Option<&str>, duration: f64) -> LibraryEntry {
+        LibraryEntry {
+            path: path.to_string(),
+            hash: path.to_string(),
+            size: 0,
+            modified: 0,
+            format: Path::new(path)
+                .extension()
+                .map(|extension| extension.to_string_lossy().to_string()),
+            format_long: None,
+            original_format: None,
+            original_format_long: None,
+            container: None,
+            container_long: None,
+            tracker: None,
+            artist: artist.map(str::to_string),
+            title: title.map(str::to_string),
+            date: None,
+            message: None,
+            message_raw: None,
+            warnings: None,
+            duration,
+            channels: 4,
+            subsongs: 1,
+        }
+    }
+
+    fn fill(connection: &Connection) {
+        for entry in [
+            entry(
+                "/music/track10.mod",
+                Some("Satellite One"),
+                Some("Purple Motion"),
+                300.0,
+            ),
+            entry("/music/track2.xm", Some("Hippos"), Some("Unknown"), 90.0),
+            entry("/music/Track3.it", None, Some("purple motion"), 120.0),
+            entry("/musical/other.mod", Some("Elsewhere"), None, 60.0),
+        ] {
+            entry.insert(connection, "/music").unwrap();
+        }
+    }
+
+    fn paths(connection: &Connection, query: LibraryQuery) -> Vec<String> {
+        let (sql, params) = query.to_sql();
+        connection
+            .prepare(&sql)
+            .unwrap()
+            .query_map(
+                rusqlite::params_from_iter(params.iter().map(|param| param.as_ref())),
+                |row| row.get::<_, String>(0),
+            )
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn natural_collation() {
+        let connection = open_temporary_database().unwrap();
+        fill(&connection);
+        let sorted: Vec<String> = connection
+            .prepare("SELECT path FROM modules WHERE root = '/music' ORDER BY path COLLATE natural_order")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(
+            sorted,
+            [
+                "/music/track2.xm",
+                "/music/Track3.it",
+                "/music/track10.mod",
+                "/musical/other.mod"
+            ]
+        );
+    }
+
+    #[test]
+    fn query_without_filters() {
+        let (sql, params) = LibraryQuery::default().to_sql();
+        assert!(!sql.contains("WHERE"));
+        assert!(sql.ends_with(
+            "ORDER BY path COLLATE natural_order ASC, path COLLATE natural_order ASC LIMIT ? OFFSET ?"
+        ));
+        assert_eq!(params.len(), 2);
+    }
+
+    #[test]
+    fn query_filters() {
+        let connection = open_temporary_database().unwrap();
+        fill(&connection);
+        let query = |query: LibraryQuery| paths(&connection, query);
+
+        // Every word has to match somewhere, regardless of case
+        assert_eq!(
+            query(LibraryQuery {
+                search: Some(String::from("PURPLE satellite")),
+                ..LibraryQuery::default()
+            }),
+            ["/music/track10.mod"]
+        );
+        assert_eq!(
+            query(LibraryQuery {
+                artist: Some(String::from("Purple Motion")),
+                ..LibraryQuery::default()
+            }),
+            ["/music/Track3.it", "/music/track10.mod"]
+        );
+        assert_eq!(
+            query(LibraryQuery {
+                format: Some(String::from("MOD")),
+                ..LibraryQuery::default()
+            }),
+            ["/music/track10.mod", "/musical/other.mod"]
+        );
+        // A folder doesn't match other folders sharing its name as prefix
+        assert_eq!(
+            query(LibraryQuery {
+                folder: Some(String::from("/musical")),
+                ..LibraryQuery::default()
+            }),
+            ["/musical/other.mod"]
+        );
+        assert_eq!(
+            query(LibraryQuery {
+                min_duration: Some(90.0),
+                max_duration: Some(120.0),
+                ..LibraryQuery::default()
+            }),
+            ["/music/track2.xm", "/music/Track3.it"]
+        );
+    }
+
+    #[test]
+    fn query_order_and_paging() {
+        let connection = open_temporary_database().unwrap();
+        fill(&connection);
+        let query = |query: LibraryQuery| paths(&connection, query);
+
+        // Modules without title are sorted by their path
+        assert_eq!(
+            query(LibraryQuery {
+                sort: LibrarySort::Title,
+                ..LibraryQuery::default()
+            }),
+            [
+                "/music/Track3.it",
+                "/musical/other.mod",
+                "/music/track2.xm",
+                "/music/track10.mod"
+            ]
+        );
+        assert_eq!(
+            query(LibraryQuery {
+                sort: LibrarySort::Duration,
+                descending: true,
+                limit: Some(2),
+                offset: 1,
+                ..LibraryQuery::default()
+            }),
+            ["/music/Track3.it", "/music/track2.xm"]
+        );
+    }
+
+    #[test]
+    fn playlist_from_query() {
+        let dir = tempfile::tempdir().unwrap();
+        let (sender, _events) = unbounded();
+        let library = Library::spawn(&dir.path().join("library.sqlite"), sender).unwrap();
+        fill(&library.connection.lock().unwrap());
+
+        let files: Vec<String> = library
+            .query(&LibraryQuery {
+                folder: Some(String::from("/music")),
+                ..LibraryQuery::default()
+            })
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.path)
+            .collect();
+        assert_eq!(
+            files,
+            ["/music/track2.xm", "/music/Track3.it", "/music/track10.mod"]
+        );
+        assert_eq!(library.status().modules, 4);
+    }
+
+    #[test]
+    fn ratings() {
+        let dir = tempfile::tempdir().unwrap();
+        let (sender, _events) = unbounded();
+        let library = Library::spawn(&dir.path().join("library.sqlite"), sender).unwrap();
+        assert!(library.ratings().unwrap().is_empty());
+
+        library.set_rating("/music/a.mod", Some(5)).unwrap();
+        library.set_rating("/music/b.mod", Some(2)).unwrap();
+        library.set_rating("/music/b.mod", Some(1)).unwrap();
+        library.set_rating("/music/c.mod", Some(3)).unwrap();
+        library.set_rating("/music/c.mod", None).unwrap();
+        assert_eq!(
+            library.ratings().unwrap(),
+            HashMap::from([
+                (String::from("/music/a.mod"), 5),
+                (String::from("/music/b.mod"), 1),
+            ])
+        );
+    }
+
+    fn indexer() -> Indexer {
+        let (_, receiver) = unbounded();
+        let (event_sender, _) = unbounded();
+        Indexer {
+            receiver,
+            connection: Arc::new(Mutex::new(open_temporary_database().unwrap())),
+            status: Arc::new(Mutex::new(LibraryStatus::default())),
+            event_sender,
+        }
+    }
+
+    fn set_modified(path: &Path, seconds: u64) {
+        std::fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(UNIX_EPOCH + Duration::from_secs(seconds))
+            .unwrap();
+    }
+
+    fn changed(checked: Result<Checked>) -> LibraryEntry {
+        match checked.unwrap() {
+            Checked::Changed(entry) => *entry,
+            Checked::Unchanged => panic!("unchanged"),
+            Checked::Unplayable { .. } => panic!("unplayable"),
+        }
+    }
+
+    #[test]
+    fn indexer_reads_new_and_changed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap();
+        let path = dir.path().join("a.mod");
+        std::fs::copy(MODULE, &path).unwrap();
+        set_modified(&path, 1000);
+        let filepath = path.to_str().unwrap();
+
+        let indexer = indexer();
+        let entry = changed(indexer.index(filepath));
+        assert_eq!(entry.modified, 1000);
+        let hash = entry.hash.clone();
+        let batch = Batch {
+            changed: vec![entry],
+            ..Batch::default()
+        };
+        indexer.write(root, &batch).unwrap();
+        assert!(matches!(indexer.index(filepath), Ok(Checked::Unchanged)));
+
+        // A touched file is checked again, but keeps its hash
+        set_modified(&path, 2000);
+        let entry = changed(indexer.index(filepath));
+        assert_eq!((entry.modified, entry.hash.as_str()), (2000, hash.as_str()));
+
+        // Copies are recognized by their hash
+        let copy = dir.path().join("b.mod");
+        std::fs::copy(MODULE, &copy).unwrap();
+        let entry = changed(indexer.index(copy.to_str().unwrap()));
+        assert_eq!(entry.path, copy.to_str().unwrap());
+        assert_eq!(entry.hash, hash);
+
+        // Changed contents result in a new hash
+        let mut data = std::fs::read(MODULE).unwrap();
+        data.extend_from_slice(&[0; 16]);
+        std::fs::write(&path, data).unwrap();
+        assert_ne!(changed(indexer.index(filepath)).hash, hash);
+    }
+
+    #[test]
+    fn indexer_skips_known_unplayable_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap();
+        let path = dir.path().join("broken.mod");
+        let size = std::fs::metadata(MODULE).unwrap().len() as usize;
+        std::fs::write(&path, vec![b'x'; size]).unwrap();
+        set_modified(&path, 1000);
+        let filepath = path.to_str().unwrap();
+
+        let indexer = indexer();
+        let Ok(Checked::Unplayable { size, modified }) = indexer.index(filepath) else {
+            panic!("broken module has been loaded");
+        };
+        let batch = Batch {
+            unplayable: vec![UnplayableFile {
+                path: filepath.to_string(),
+                size,
+                modified,
+            }],
+            ..Batch::default()
+        };
+        indexer.write(root, &batch).unwrap();
+
+        // The file isn't read again, while size and time stay the same
+        std::fs::copy(MODULE, &path).unwrap();
+        set_modified(&path, 1000);
+        assert!(matches!(
+            indexer.index(filepath),
+            Ok(Checked::Unplayable { .. })
+        ));
+
+        set_modified(&path, 2000);
+        let batch = Batch {
+            changed: vec![changed(indexer.index(filepath))],
+            ..Batch::default()
+        };
+        indexer.write(root, &batch).unwrap();
+        assert!(matches!(indexer.index(filepath), Ok(Checked::Unchanged)));
+    }
+
+    #[test]
+    fn indexer_removes_missing_files_and_roots() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap().to_string();
+        for name in ["a.mod", "b.mod"] {
+            std::fs::copy(MODULE, dir.path().join(name)).unwrap();
+        }
+        std::fs::write(dir.path().join("broken.mod"), b"broken").unwrap();
+
+        let indexer = indexer();
+        let count = |table: &str| -> i64 {
+            indexer
+                .connection
+                .lock()
+                .unwrap()
+                .query_row(&format!("SELECT count(*) FROM {}", table), [], |row| {
+                    row.get(0)
+                })
+                .unwrap()
+        };
+
+        assert!(indexer.update(std::slice::from_ref(&root)).is_none());
+        assert_eq!((count("modules"), count("unplayable")), (2, 1));
+
+        std::fs::remove_file(dir.path().join("b.mod")).unwrap();
+        std::fs::remove_file(dir.path().join("broken.mod")).unwrap();
+        indexer.update(std::slice::from_ref(&root));
+        assert_eq!((count("modules"), count("unplayable")), (1, 0));
+
+        // An unavailable root keeps its modules
+        indexer.update(&[root.clone(), String::from("/does/not/exist")]);
+        assert_eq!(count("modules"), 1);
+
+        indexer.update(&[]);
+        assert_eq!(count("modules"), 0);
+    }
+}
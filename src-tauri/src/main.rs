@@ -1,19 +1,34 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+use config::Config;
 use player::Player;
 #[cfg(target_os = "macos")]
 use tauri::ActivationPolicy;
 use std::sync::Mutex;
+use tauri::Manager;
 
 mod commands;
+mod config;
+mod dsp;
+mod entry_info;
+mod error;
+mod events;
+mod library;
+mod normalization;
 mod openmpt;
+mod output;
 mod player;
+mod playlist_file;
+mod preload;
+mod scan;
+mod scope;
+mod shuffle;
+mod spectrum;
+mod transport;
 mod tray;
 
 fn main() {
-    let player = Player::spawn();
-
     tauri::Builder::default()
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_positioner::init())
@@ -25,11 +40,71 @@ fn main() {
             commands::next_module,
             commands::previous_module,
             commands::seek_module,
+            commands::get_playlist,
+            commands::append_to_playlist,
+            commands::insert_next,
+            commands::remove_from_playlist,
+            commands::move_playlist_entry,
+            commands::clear_playlist,
+            commands::jump_to_playlist_entry,
+            commands::get_equalizer,
+            commands::get_equalizer_presets,
+            commands::set_equalizer_enabled,
+            commands::set_equalizer_band,
+            commands::set_equalizer_bands,
+            commands::apply_equalizer_preset,
+            commands::save_equalizer_preset,
+            commands::delete_equalizer_preset,
+            commands::get_crossfeed,
+            commands::set_crossfeed_enabled,
+            commands::set_crossfeed_preset,
+            commands::set_crossfeed_levels,
+            commands::get_normalization,
+            commands::set_normalization_mode,
+            commands::set_normalization_target,
+            commands::set_normalization_prevent_clipping,
+            commands::get_limiter,
+            commands::set_limiter_enabled,
+            commands::set_limiter_ceiling,
+            commands::get_player_state,
             commands::subscribe_to_player_events,
-            commands::unsubscribe_from_player_events
+            commands::unsubscribe_from_player_events,
+            commands::get_spectrum_settings,
+            commands::set_spectrum_settings,
+            commands::subscribe_to_spectrum,
+            commands::unsubscribe_from_spectrum,
+            commands::get_scope_settings,
+            commands::set_scope_settings,
+            commands::subscribe_to_scope,
+            commands::unsubscribe_from_scope,
+            commands::get_output_hosts,
+            commands::get_output_devices,
+            commands::get_output_settings,
+            commands::select_output_device,
+            commands::set_pause_on_device_loss,
+            commands::get_preload_settings,
+            commands::set_preload_memory_budget,
+            commands::get_shuffle_mode,
+            commands::set_shuffle_mode,
+            commands::get_repeat_mode,
+            commands::set_repeat_mode,
+            commands::open_playlist,
+            commands::save_playlist,
+            commands::get_library_roots,
+            commands::set_library_roots,
+            commands::update_library,
+            commands::get_library_status,
+            commands::query_library,
+            commands::get_ratings,
+            commands::set_rating,
+            commands::load_from_library
         ])
-        .manage(Mutex::new(player))
         .setup(|app| {
+            let config = Config::load(&app.path().app_config_dir()?.join("config.json"));
+            let cache_dir = app.path().app_cache_dir()?;
+            let data_dir = app.path().app_data_dir()?;
+            app.manage(Mutex::new(Player::spawn(config, cache_dir, data_dir)));
+
             #[cfg(target_os = "macos")]
             {
                 tray::init_macos_menu_extra(app.handle())?;
@@ -0,0 +1,499 @@
+use crate::dsp::loudness::LoudnessMeter;
+use crate::openmpt;
+use anyhow::Result;
+use crossbeam::channel::{unbounded, Receiver, Sender, TryRecvError};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+const ANALYSIS_SAMPLE_RATE: u32 = 48_000;
+const ANALYSIS_CHUNK_FRAMES: usize = 4096;
+/// Upper bound for rendering modules, which never signal their end
+const MAX_ANALYSIS_SECONDS: f64 = 30.0 * 60.0;
+/// Number of newly analyzed files after which the cache is written to disk
+const CACHE_SAVE_INTERVAL: usize = 16;
+
+/// Maximum true peak allowed after normalization if clipping prevention is
+/// active
+const TRUE_PEAK_CEILING: f64 = -1.0;
+const MIN_GAIN: f64 = -24.0;
+const MAX_GAIN: f64 = 12.0;
+
+#[derive(PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoudnessInfo {
+    /// Integrated loudness in LUFS
+    pub integrated: f64,
+    /// True peak in dBTP
+    pub true_peak: f64,
+    pub duration: f64,
+}
+
+impl LoudnessInfo {
+    /// Render the given module data offline and measure it
+    pub fn analyze(data: &[u8]) -> Result<Self> {
+        let mut module = openmpt::module::Module::try_from_memory(data)?;
+        let mut meter = LoudnessMeter::new(ANALYSIS_SAMPLE_RATE);
+        let mut buffer = vec![0f32; ANALYSIS_CHUNK_FRAMES * 2];
+
+        while meter.duration_seconds() < MAX_ANALYSIS_SECONDS {
+            let frames = module.read_frames(ANALYSIS_SAMPLE_RATE as i32, &mut buffer);
+            if frames == 0 {
+                break;
+            }
+            meter.process(&buffer[..frames * 2]);
+        }
+
+        Ok(Self {
+            integrated: meter.integrated_loudness(),
+            true_peak: meter.true_peak(),
+            duration: meter.duration_seconds(),
+        })
+    }
+
+    /// Combine the measurements of multiple tracks into one for the whole
+    /// folder.
+    ///
+    /// This is an approximation of measuring all tracks as one stream: The
+    /// loudness is the duration weighted energy mean of all tracks.
+    fn combine<'a>(infos: impl Iterator<Item = &'a LoudnessInfo>) -> Option<Self> {
+        let mut energy = 0.0;
+        let mut true_peak = f64::MIN;
+        let mut duration = 0.0;
+        for info in infos {
+            energy += info.duration * 10f64.powf(info.integrated / 10.0);
+            true_peak = true_peak.max(info.true_peak);
+            duration += info.duration;
+        }
+
+        if duration <= 0.0 {
+            return None;
+        }
+
+        Some(Self {
+            integrated: 10.0 * (energy / duration).log10(),
+            true_peak,
+            duration,
+        })
+    }
+}
+
+/// Persisted analysis results keyed by the SHA-256 hash of the module file
+#[derive(Default, Serialize, Deserialize)]
+struct LoudnessCache {
+    entries: HashMap<String, LoudnessInfo>,
+}
+
+impl LoudnessCache {
+    fn load(path: &Path) -> Self {
+        match std::fs::read(path) {
+            Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|err| {
+                eprintln!("Could not parse loudness cache {}: {}", path.display(), err);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_vec(self)?)?;
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct LoudnessResults {
+    tracks: HashMap<String, LoudnessInfo>,
+    folders: HashMap<PathBuf, LoudnessInfo>,
+}
+
+enum AnalyzerRequest {
+    /// Replace the list of files to analyze in the background
+    Queue(Vec<String>),
+    /// Add files to the end of the list
+    Append(Vec<String>),
+    /// Analyze the given file next
+    Prioritize(String),
+}
+
+fn folder_of(filepath: &str) -> PathBuf {
+    Path::new(filepath)
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default()
+}
+
+struct AnalyzerWorker {
+    receiver: Receiver<AnalyzerRequest>,
+    results: Arc<Mutex<LoudnessResults>>,
+    cache: LoudnessCache,
+    cache_path: PathBuf,
+    unsaved: usize,
+    queue: VecDeque<String>,
+    folders: HashMap<PathBuf, Vec<String>>,
+    /// Every file which has been handled, even if it could not be analyzed
+    done: HashSet<String>,
+}
+
+impl AnalyzerWorker {
+    fn run(mut self) {
+        loop {
+            if self.queue.is_empty() {
+                self.save_cache();
+                match self.receiver.recv() {
+                    Ok(request) => self.handle_request(request),
+                    Err(_) => break,
+                }
+            }
+
+            loop {
+                match self.receiver.try_recv() {
+                    Ok(request) => self.handle_request(request),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        self.save_cache();
+                        return;
+                    }
+                }
+            }
+
+            if let Some(filepath) = self.queue.pop_front() {
+                self.analyze(filepath);
+            }
+        }
+        self.save_cache();
+    }
+
+    fn handle_request(&mut self, request: AnalyzerRequest) {
+        match request {
+            AnalyzerRequest::Queue(files) => {
+                self.folders.clear();
+                for filepath in files.iter() {
+                    self.folders
+                        .entry(folder_of(filepath))
+                        .or_default()
+                        .push(filepath.clone());
+                }
+                self.queue = files
+                    .into_iter()
+                    .filter(|filepath| !self.done.contains(filepath))
+                    .collect();
+
+                let folders: Vec<PathBuf> = self.folders.keys().cloned().collect();
+                for folder in folders {
+                    self.update_folder(&folder);
+                }
+            }
+            AnalyzerRequest::Append(files) => {
+                let mut folders = HashSet::new();
+                for filepath in files.iter() {
+                    let folder = folder_of(filepath);
+                    self.folders
+                        .entry(folder.clone())
+                        .or_default()
+                        .push(filepath.clone());
+                    folders.insert(folder);
+                }
+                self.queue.extend(
+                    files
+                        .into_iter()
+                        .filter(|filepath| !self.done.contains(filepath)),
+                );
+
+                for folder in folders {
+                    self.update_folder(&folder);
+                }
+            }
+            AnalyzerRequest::Prioritize(filepath) => {
+                if !self.done.contains(&filepath) {
+                    self.queue.retain(|candidate| *candidate != filepath);
+                    self.queue.push_front(filepath);
+                }
+            }
+        }
+    }
+
+    fn analyze(&mut self, filepath: String) {
+        if self.done.contains(&filepath) {
+            return;
+        }
+
+        match self.lookup_or_analyze(&filepath) {
+            Ok(info) => {
+                self.results
+                    .lock()
+                    .unwrap()
+                    .tracks
+                    .insert(filepath.clone(), info);
+            }
+            Err(err) => eprintln!("Could not analyze loudness of {}: {}", filepath, err),
+        }
+
+        self.done.insert(filepath.clone());
+        self.update_folder(&folder_of(&filepath));
+    }
+
+    fn lookup_or_analyze(&mut self, filepath: &str) -> Result<LoudnessInfo> {
+        let data = std::fs::read(filepath)?;
+        let hash = format!("{:x}", Sha256::digest(&data));
+        if let Some(info) = self.cache.entries.get(&hash) {
+            return Ok(*info);
+        }
+
+        eprintln!("Analyzing loudness of {}", filepath);
+        let info = LoudnessInfo::analyze(&data)?;
+        self.cache.entries.insert(hash, info);
+        self.unsaved += 1;
+        if self.unsaved >= CACHE_SAVE_INTERVAL {
+            self.save_cache();
+        }
+        Ok(info)
+    }
+
+    /// Calculate the folder loudness once all of its files have been handled
+    fn update_folder(&mut self, folder: &PathBuf) {
+        let Some(files) = self.folders.get(folder) else {
+            return;
+        };
+        if !files.iter().all(|filepath| self.done.contains(filepath)) {
+            return;
+        }
+
+        let mut results = self.results.lock().unwrap();
+        let combined = LoudnessInfo::combine(
+            files
+                .iter()
+                .filter_map(|filepath| results.tracks.get(filepath)),
+        );
+        if let Some(info) = combined {
+            results.folders.insert(folder.clone(), info);
+        }
+    }
+
+    fn save_cache(&mut self) {
+        if self.unsaved == 0 {
+            return;
+        }
+        if let Err(err) = self.cache.save(&self.cache_path) {
+            eprintln!("Could not save loudness cache: {}", err);
+        }
+        self.unsaved = 0;
+    }
+}
+
+/// Background worker measuring the loudness of queued modules
+pub struct LoudnessAnalyzer {
+    sender: Sender<AnalyzerRequest>,
+    results: Arc<Mutex<LoudnessResults>>,
+}
+
+impl LoudnessAnalyzer {
+    pub fn spawn(cache_path: PathBuf) -> Self {
+        let (sender, receiver) = unbounded();
+        let results = Arc::new(Mutex::new(LoudnessResults::default()));
+
+        let worker = AnalyzerWorker {
+            receiver,
+            results: results.clone(),
+            cache: LoudnessCache::load(&cache_path),
+            cache_path,
+            unsaved: 0,
+            queue: VecDeque::new(),
+            folders: HashMap::new(),
+            done: HashSet::new(),
+        };
+        std::thread::spawn(move || worker.run());
+
+        Self { sender, results }
+    }
+
+    fn send(&self, request: AnalyzerRequest) {
+        if self.sender.send(request).is_err() {
+            eprintln!("Loudness analyzer is not running");
+        }
+    }
+
+    pub fn queue(&self, files: Vec<String>) {
+        self.send(AnalyzerRequest::Queue(files));
+    }
+
+    pub fn append(&self, files: Vec<String>) {
+        self.send(AnalyzerRequest::Append(files));
+    }
+
+    pub fn prioritize(&self, filepath: &str) {
+        self.send(AnalyzerRequest::Prioritize(filepath.to_string()));
+    }
+}
+
+#[derive(Eq, PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum NormalizationMode {
+    Off,
+    Track,
+    Folder,
+}
+
+#[derive(PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct NormalizationSettings {
+    pub mode: NormalizationMode,
+    /// Target loudness in LUFS
+    pub target: f64,
+    /// Limit the gain, so that the true peak stays below -1 dBTP
+    pub prevent_clipping: bool,
+}
+
+impl Default for NormalizationSettings {
+    fn default() -> Self {
+        Self {
+            mode: NormalizationMode::Off,
+            target: -18.0,
+            prevent_clipping: true,
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Copy, Clone)]
+enum Resolution {
+    Pending,
+    Track,
+    Folder,
+}
+
+/// Applies a gain to the rendered audio to reach the configured target
+/// loudness, based upon the results of the `LoudnessAnalyzer`.
+pub struct Normalizer {
+    settings: NormalizationSettings,
+    analyzer: LoudnessAnalyzer,
+    files: Vec<String>,
+    current: Option<String>,
+    resolution: Resolution,
+    gain: f32,
+    target_gain: f32,
+}
+
+impl Normalizer {
+    pub fn new(settings: NormalizationSettings, analyzer: LoudnessAnalyzer) -> Self {
+        Self {
+            settings,
+            analyzer,
+            files: vec![],
+            current: None,
+            resolution: Resolution::Pending,
+            gain: 1.0,
+            target_gain: 1.0,
+        }
+    }
+
+    fn enabled(&self) -> bool {
+        self.settings.mode != NormalizationMode::Off
+    }
+
+    pub fn configure(&mut self, settings: NormalizationSettings) {
+        let was_enabled = self.enabled();
+        self.settings = settings;
+        self.resolution = Resolution::Pending;
+
+        if self.enabled() && !was_enabled {
+            self.analyzer.queue(self.files.clone());
+            if let Some(ref current) = self.current {
+                self.analyzer.prioritize(current);
+            }
+        }
+    }
+
+    pub fn set_playlist(&mut self, files: &[String]) {
+        self.files = files.to_vec();
+        if self.enabled() {
+            self.analyzer.queue(self.files.clone());
+        }
+    }
+
+    /// Like `set_playlist`, but only for files added to the end
+    pub fn append_playlist(&mut self, files: &[String]) {
+        self.files.extend_from_slice(files);
+        if self.enabled() {
+            self.analyzer.append(files.to_vec());
+        }
+    }
+
+    pub fn set_track(&mut self, filepath: &str) {
+        self.current = Some(filepath.to_string());
+        self.resolution = Resolution::Pending;
+        // Never apply the gain of the previous track. A known folder gain is
+        // picked up by the next `resolve`.
+        self.target_gain = 1.0;
+        if self.enabled() {
+            self.analyzer.prioritize(filepath);
+        }
+    }
+
+    fn gain_for(&self, info: &LoudnessInfo) -> f32 {
+        let mut gain = self.settings.target - info.integrated;
+        if self.settings.prevent_clipping {
+            gain = gain.min(TRUE_PEAK_CEILING - info.true_peak);
+        }
+        10f64.powf(gain.clamp(MIN_GAIN, MAX_GAIN) / 20.0) as f32
+    }
+
+    /// Look up the gain of the current track. This runs on the audio thread,
+    /// therefore it never waits for the analyzer.
+    fn resolve(&mut self) {
+        let wanted = match self.settings.mode {
+            NormalizationMode::Off => return,
+            NormalizationMode::Track => Resolution::Track,
+            NormalizationMode::Folder => Resolution::Folder,
+        };
+        if self.resolution == wanted {
+            return;
+        }
+        let Some(ref current) = self.current else {
+            return;
+        };
+        let Ok(results) = self.analyzer.results.try_lock() else {
+            return;
+        };
+
+        if wanted == Resolution::Folder {
+            if let Some(info) = results.folders.get(&folder_of(current)) {
+                self.target_gain = self.gain_for(info);
+                self.resolution = Resolution::Folder;
+                return;
+            }
+        }
+
+        // Folder mode uses the track gain until the whole folder is analyzed
+        if self.resolution == Resolution::Pending {
+            if let Some(info) = results.tracks.get(current) {
+                self.target_gain = self.gain_for(info);
+                self.resolution = Resolution::Track;
+            }
+        }
+    }
+
+    pub fn process(&mut self, data: &mut [f32]) {
+        let target_gain = if self.enabled() {
+            self.resolve();
+            self.target_gain
+        } else {
+            1.0
+        };
+
+        // Ramp to the new gain over the length of the buffer, to prevent
+        // audible steps
+        let frames = (data.len() / 2).max(1);
+        let step = (target_gain - self.gain) / frames as f32;
+        for frame in data.chunks_exact_mut(2) {
+            self.gain += step;
+            frame[0] *= self.gain;
+            frame[1] *= self.gain;
+        }
+        self.gain = target_gain;
+    }
+}
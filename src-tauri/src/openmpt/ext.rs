@@ -0,0 +1,66 @@
+//! Bindings for the parts of `libopenmpt_ext.h`, which are not covered by
+//! libopenmpt-sys. The symbols are part of the regular libopenmpt library.
+#![allow(non_camel_case_types)]
+
+use libopenmpt_sys::{
+    openmpt_error_func, openmpt_log_func, openmpt_module, openmpt_module_initial_ctl,
+};
+use std::ffi::{c_char, c_int, c_void};
+
+pub const INTERFACE_INTERACTIVE: &[u8] = b"interactive\0";
+
+#[repr(C)]
+pub struct openmpt_module_ext {
+    _unused: [u8; 0],
+}
+
+#[repr(C)]
+#[derive(Default)]
+pub struct openmpt_module_ext_interface_interactive {
+    pub set_current_speed: Option<unsafe extern "C" fn(*mut openmpt_module_ext, i32) -> c_int>,
+    pub set_current_tempo: Option<unsafe extern "C" fn(*mut openmpt_module_ext, i32) -> c_int>,
+    pub set_tempo_factor: Option<unsafe extern "C" fn(*mut openmpt_module_ext, f64) -> c_int>,
+    pub get_tempo_factor: Option<unsafe extern "C" fn(*mut openmpt_module_ext) -> f64>,
+    pub set_pitch_factor: Option<unsafe extern "C" fn(*mut openmpt_module_ext, f64) -> c_int>,
+    pub get_pitch_factor: Option<unsafe extern "C" fn(*mut openmpt_module_ext) -> f64>,
+    pub set_global_volume: Option<unsafe extern "C" fn(*mut openmpt_module_ext, f64) -> c_int>,
+    pub get_global_volume: Option<unsafe extern "C" fn(*mut openmpt_module_ext) -> f64>,
+    pub set_channel_volume:
+        Option<unsafe extern "C" fn(*mut openmpt_module_ext, i32, f64) -> c_int>,
+    pub get_channel_volume: Option<unsafe extern "C" fn(*mut openmpt_module_ext, i32) -> f64>,
+    pub set_channel_mute_status:
+        Option<unsafe extern "C" fn(*mut openmpt_module_ext, i32, c_int) -> c_int>,
+    pub get_channel_mute_status:
+        Option<unsafe extern "C" fn(*mut openmpt_module_ext, i32) -> c_int>,
+    pub set_instrument_mute_status:
+        Option<unsafe extern "C" fn(*mut openmpt_module_ext, i32, c_int) -> c_int>,
+    pub get_instrument_mute_status:
+        Option<unsafe extern "C" fn(*mut openmpt_module_ext, i32) -> c_int>,
+    pub play_note: Option<unsafe extern "C" fn(*mut openmpt_module_ext, i32, i32, f64, f64) -> i32>,
+    pub stop_note: Option<unsafe extern "C" fn(*mut openmpt_module_ext, i32) -> c_int>,
+}
+
+extern "C" {
+    pub fn openmpt_module_ext_create_from_memory(
+        filedata: *const c_void,
+        filesize: usize,
+        logfunc: openmpt_log_func,
+        loguser: *mut c_void,
+        errfunc: openmpt_error_func,
+        erruser: *mut c_void,
+        error: *mut c_int,
+        error_message: *mut *const c_char,
+        ctls: *const openmpt_module_initial_ctl,
+    ) -> *mut openmpt_module_ext;
+
+    pub fn openmpt_module_ext_destroy(mod_ext: *mut openmpt_module_ext);
+
+    pub fn openmpt_module_ext_get_module(mod_ext: *mut openmpt_module_ext) -> *mut openmpt_module;
+
+    pub fn openmpt_module_ext_get_interface(
+        mod_ext: *mut openmpt_module_ext,
+        interface_id: *const c_char,
+        interface: *mut c_void,
+        interface_size: usize,
+    ) -> c_int;
+}
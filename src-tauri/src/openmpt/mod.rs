@@ -1 +1,19 @@
+mod ext;
 pub mod module;
+
+use std::ffi::CStr;
+
+/// File extensions of all formats libopenmpt is able to play
+pub fn supported_extensions() -> Vec<String> {
+    unsafe {
+        let extensions = libopenmpt_sys::openmpt_get_supported_extensions();
+        let result = CStr::from_ptr(extensions)
+            .to_string_lossy()
+            .split(';')
+            .filter(|extension| !extension.is_empty())
+            .map(str::to_string)
+            .collect();
+        libopenmpt_sys::openmpt_free_string(extensions);
+        result
+    }
+}
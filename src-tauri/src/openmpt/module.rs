@@ -10,6 +10,8 @@ use std::sync::Arc;
 
 use libopenmpt_sys::*;
 
+use super::ext::*;
+
 #[derive(Eq, PartialEq, Clone, Serialize)]
 #[serde(rename_all = "camelCase", tag = "key", content = "value")]
 pub enum Metadata {
@@ -30,12 +32,16 @@ pub enum Metadata {
 
 pub struct Module {
     handle: *mut openmpt_module,
+    /// Only set for modules created with `try_from_memory_interactive`. It
+    /// owns `handle` in that case.
+    ext: *mut openmpt_module_ext,
+    interactive: Option<openmpt_module_ext_interface_interactive>,
     playback_end: Arc<AtomicBool>,
     metadata: RefCell<Option<Arc<Vec<Metadata>>>>,
 }
 
 impl Module {
-    pub fn try_from_memory(data: &Vec<u8>) -> Result<Self> {
+    pub fn try_from_memory(data: &[u8]) -> Result<Self> {
         let handle = unsafe {
             libopenmpt_sys::openmpt_module_create_from_memory2(
                 data.as_ptr() as *const c_void,
@@ -57,21 +63,98 @@ impl Module {
 
         Ok(Self {
             handle,
+            ext: std::ptr::null_mut(),
+            interactive: None,
             playback_end: Arc::new(AtomicBool::new(false)),
             metadata: RefCell::new(None),
         })
     }
 
-    pub fn read(&mut self, rate: i32, data: &mut [f32]) -> bool {
-        let n_read = unsafe {
+    /// Load a module using the extended libopenmpt API, which allows to
+    /// interactively change playback, like muting single channels.
+    pub fn try_from_memory_interactive(data: &[u8]) -> Result<Self> {
+        let ext = unsafe {
+            openmpt_module_ext_create_from_memory(
+                data.as_ptr() as *const c_void,
+                data.len(),
+                None,
+                std::ptr::null_mut(),
+                None,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                std::ptr::null(),
+            )
+        };
+        if ext.is_null() {
+            return Err(anyhow!("Could not load Module file"));
+        }
+
+        let mut interactive = openmpt_module_ext_interface_interactive::default();
+        let found = unsafe {
+            openmpt_module_ext_get_interface(
+                ext,
+                INTERFACE_INTERACTIVE.as_ptr() as *const _,
+                &mut interactive as *mut _ as *mut c_void,
+                std::mem::size_of::<openmpt_module_ext_interface_interactive>(),
+            )
+        };
+        if found == 0 {
+            unsafe { openmpt_module_ext_destroy(ext) };
+            return Err(anyhow!("Interactive interface is not supported"));
+        }
+
+        Ok(Self {
+            handle: unsafe { openmpt_module_ext_get_module(ext) },
+            ext,
+            interactive: Some(interactive),
+            playback_end: Arc::new(AtomicBool::new(false)),
+            metadata: RefCell::new(None),
+        })
+    }
+
+    /// Render interleaved stereo data and return the number of frames, which
+    /// have actually been written. Everything after those is left untouched.
+    pub fn read_frames(&mut self, rate: i32, data: &mut [f32]) -> usize {
+        unsafe {
             openmpt_module_read_interleaved_float_stereo(
                 self.handle,
                 rate,
                 data.len() / 2,
                 data.as_mut_ptr(),
             )
+        }
+    }
+
+    /// Render mono data and return the number of frames actually written
+    pub fn read_mono(&mut self, rate: i32, data: &mut [f32]) -> usize {
+        unsafe { openmpt_module_read_float_mono(self.handle, rate, data.len(), data.as_mut_ptr()) }
+    }
+
+    pub fn get_num_channels(&self) -> i32 {
+        unsafe { openmpt_module_get_num_channels(self.handle) }
+    }
+
+    pub fn set_channel_mute(&mut self, channel: i32, mute: bool) -> Result<()> {
+        let Some(set_channel_mute_status) = self
+            .interactive
+            .as_ref()
+            .and_then(|interactive| interactive.set_channel_mute_status)
+        else {
+            return Err(anyhow!("Module has not been loaded interactively"));
         };
-        return n_read != 0;
+
+        if unsafe { set_channel_mute_status(self.ext, channel, mute as _) } == 0 {
+            return Err(anyhow!(
+                "Could not change mute status of channel {}",
+                channel
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn get_num_subsongs(&self) -> i32 {
+        unsafe { openmpt_module_get_num_subsongs(self.handle) }
     }
 
     pub fn get_duration_seconds(&self) -> f64 {
@@ -139,5 +222,15 @@ impl Module {
     }
 }
 
+impl Drop for Module {
+    fn drop(&mut self) {
+        if self.ext.is_null() {
+            unsafe { openmpt_module_destroy(self.handle) }
+        } else {
+            unsafe { openmpt_module_ext_destroy(self.ext) }
+        }
+    }
+}
+
 // @TODO: Is this really needed? For what exactly
 unsafe impl Send for Module {}
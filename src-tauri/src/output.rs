@@ -0,0 +1,293 @@
+use anyhow::{anyhow, bail, Result};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{
+    FromSample, SampleFormat, SampleRate, SizedSample, StreamConfig, StreamError,
+    SupportedStreamConfig,
+};
+use ringbuf::traits::{Consumer, Observer, Split};
+use ringbuf::{HeapCons, HeapProd, HeapRb};
+use serde::{Deserialize, Serialize};
+
+/// Used if the device doesn't report a native sample rate
+const PREFERRED_SAMPLE_RATE: SampleRate = SampleRate(48_000);
+/// Amount of audio buffered between the render thread and the output
+/// callback. This needs to exceed the buffer size requested by the device.
+const RING_SECONDS: f64 = 0.15;
+
+/// Preferred output device, persisted by name. `None` selects the system
+/// default host or device respectively.
+#[derive(Eq, PartialEq, Clone, Default, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct OutputSettings {
+    pub host: Option<String>,
+    pub device: Option<String>,
+    /// Pause playback instead of continuing on another device, if the
+    /// current one disappears
+    pub pause_on_device_loss: bool,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutputConfig {
+    pub channels: u16,
+    pub sample_format: String,
+    pub min_sample_rate: u32,
+    pub max_sample_rate: u32,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutputDevice {
+    pub host: String,
+    pub name: String,
+    pub is_default: bool,
+    pub configs: Vec<OutputConfig>,
+}
+
+/// An opened output device, which may differ from the requested one
+pub struct ResolvedDevice {
+    pub device: cpal::Device,
+    pub host: String,
+    pub name: String,
+    /// The requested device was unavailable and the default is used instead
+    pub fallback: bool,
+}
+
+pub fn hosts() -> Vec<String> {
+    cpal::available_hosts()
+        .into_iter()
+        .map(|id| id.name().to_string())
+        .collect()
+}
+
+/// Get the host with the given name, or the default host if it isn't
+/// available.
+fn find_host(name: Option<&str>) -> (cpal::Host, bool) {
+    let Some(name) = name else {
+        return (cpal::default_host(), false);
+    };
+
+    let host = cpal::available_hosts()
+        .into_iter()
+        .find(|id| id.name() == name)
+        .and_then(|id| cpal::host_from_id(id).ok());
+
+    match host {
+        Some(host) => (host, false),
+        None => (cpal::default_host(), true),
+    }
+}
+
+/// List all output devices of the given host (or the default host) together
+/// with the configurations they support.
+pub fn devices(host: Option<&str>) -> Vec<OutputDevice> {
+    let (host, _) = find_host(host);
+    let default_name = host
+        .default_output_device()
+        .and_then(|device| device.name().ok());
+
+    let devices = match host.output_devices() {
+        Ok(devices) => devices,
+        Err(err) => {
+            eprintln!("Could not enumerate output devices: {}", err);
+            return vec![];
+        }
+    };
+
+    devices
+        .filter_map(|device| {
+            let name = device.name().ok()?;
+            let configs = match device.supported_output_configs() {
+                Ok(configs) => configs
+                    .map(|cfg| OutputConfig {
+                        channels: cfg.channels(),
+                        sample_format: cfg.sample_format().to_string(),
+                        min_sample_rate: cfg.min_sample_rate().0,
+                        max_sample_rate: cfg.max_sample_rate().0,
+                    })
+                    .collect(),
+                Err(err) => {
+                    eprintln!("Could not query configs of {}: {}", name, err);
+                    vec![]
+                }
+            };
+
+            Some(OutputDevice {
+                host: host.id().name().to_string(),
+                is_default: default_name.as_ref() == Some(&name),
+                name,
+                configs,
+            })
+        })
+        .collect()
+}
+
+pub fn device_exists(host: &str, name: &str) -> bool {
+    let (host, host_missing) = find_host(Some(host));
+    if host_missing {
+        return false;
+    }
+
+    host.output_devices().is_ok_and(|mut devices| {
+        devices.any(|device| device.name().is_ok_and(|device_name| device_name == name))
+    })
+}
+
+/// Find the device described by the settings. If it is missing, the default
+/// output device of the default host is used instead.
+pub fn resolve(settings: &OutputSettings) -> Result<ResolvedDevice> {
+    let (host, host_missing) = find_host(settings.host.as_deref());
+
+    let requested = match settings.device {
+        Some(ref name) if !host_missing => host.output_devices().ok().and_then(|mut devices| {
+            devices.find(|device| device.name().ok().as_ref() == Some(name))
+        }),
+        _ => None,
+    };
+    let fallback = host_missing || (settings.device.is_some() && requested.is_none());
+
+    let device = match requested {
+        Some(device) => device,
+        None => host
+            .default_output_device()
+            .ok_or_else(|| anyhow!("No output device available"))?,
+    };
+
+    Ok(ResolvedDevice {
+        name: device.name()?,
+        host: host.id().name().to_string(),
+        device,
+        fallback,
+    })
+}
+
+/// A running output stream, which plays the interleaved stereo frames
+/// pushed into its ring buffer.
+pub struct OutputStream {
+    _stream: cpal::Stream,
+    pub producer: HeapProd<f32>,
+    pub sample_rate: u32,
+}
+
+/// Open a stream on the given device with the best available config
+pub fn open_stream(
+    device: &cpal::Device,
+    on_error: impl FnMut(StreamError) + Send + 'static,
+) -> Result<OutputStream> {
+    let supported = negotiate(device)?;
+    println!(
+        "Output config: {} Hz, {} channels, {}",
+        supported.sample_rate().0,
+        supported.channels(),
+        supported.sample_format()
+    );
+
+    let cfg = supported.config();
+    let frames = (cfg.sample_rate.0 as f64 * RING_SECONDS) as usize;
+    let (producer, consumer) = HeapRb::<f32>::new(frames * 2).split();
+
+    let stream = match supported.sample_format() {
+        SampleFormat::F32 => build_stream::<f32>(device, &cfg, consumer, on_error),
+        SampleFormat::I16 => build_stream::<i16>(device, &cfg, consumer, on_error),
+        SampleFormat::U16 => build_stream::<u16>(device, &cfg, consumer, on_error),
+        format => bail!("Unsupported sample format {}", format),
+    }?;
+
+    if let Err(err) = stream.play() {
+        eprintln!("Could not start output stream: {}", err);
+    }
+
+    Ok(OutputStream {
+        _stream: stream,
+        producer,
+        sample_rate: cfg.sample_rate.0,
+    })
+}
+
+/// Build a stream for the given sample type. The callback only drains the
+/// ring buffer and converts the rendered stereo float frames to the device
+/// format, so it never blocks.
+fn build_stream<T>(
+    device: &cpal::Device,
+    cfg: &StreamConfig,
+    mut consumer: HeapCons<f32>,
+    on_error: impl FnMut(StreamError) + Send + 'static,
+) -> Result<cpal::Stream>
+where
+    T: SizedSample + FromSample<f32>,
+{
+    let channels = cfg.channels as usize;
+    let mut buffer: Vec<f32> = Vec::with_capacity(consumer.capacity().get());
+
+    let stream = device.build_output_stream(
+        cfg,
+        move |data: &mut [T], _cpal| {
+            buffer.resize(data.len() / channels * 2, 0.0);
+            let count = consumer.pop_slice(&mut buffer);
+            // The render thread couldn't keep up
+            buffer[count..].fill(0.0);
+            write_frames(&buffer, channels, data);
+        },
+        on_error,
+        None,
+    )?;
+
+    Ok(stream)
+}
+
+/// Sample formats the output can be converted to, ordered by preference
+fn format_rank(format: SampleFormat) -> Option<u8> {
+    match format {
+        SampleFormat::F32 => Some(3),
+        SampleFormat::I16 => Some(2),
+        SampleFormat::U16 => Some(1),
+        _ => None,
+    }
+}
+
+/// Pick the best available stream config of the device. The native config
+/// is used whenever its sample format can be rendered. Otherwise stereo and
+/// float formats are preferred, running as close to the native sample rate
+/// as possible.
+pub fn negotiate(device: &cpal::Device) -> Result<SupportedStreamConfig> {
+    let native = device.default_output_config().ok();
+    if let Some(ref cfg) = native {
+        if format_rank(cfg.sample_format()).is_some() {
+            return Ok(cfg.clone());
+        }
+    }
+
+    let (_, best) = device
+        .supported_output_configs()?
+        .filter_map(|cfg| Some((format_rank(cfg.sample_format())?, cfg)))
+        .max_by_key(|(rank, cfg)| (cfg.channels() == 2, cfg.channels() > 2, *rank))
+        .ok_or_else(|| anyhow!("Output device doesn't support any usable sample format"))?;
+
+    let rate = native
+        .map_or(PREFERRED_SAMPLE_RATE, |cfg| cfg.sample_rate())
+        .clamp(best.min_sample_rate(), best.max_sample_rate());
+    Ok(best.with_sample_rate(rate))
+}
+
+/// Convert interleaved stereo frames into the device format. Mono devices
+/// get a down-mix, while additional channels of multichannel devices are
+/// left silent.
+pub fn write_frames<T>(stereo: &[f32], channels: usize, output: &mut [T])
+where
+    T: SizedSample + FromSample<f32>,
+{
+    for (frame, out) in stereo
+        .chunks_exact(2)
+        .zip(output.chunks_exact_mut(channels))
+    {
+        match out {
+            [mono] => *mono = T::from_sample((frame[0] + frame[1]) * 0.5),
+            [left, right, rest @ ..] => {
+                *left = T::from_sample(frame[0]);
+                *right = T::from_sample(frame[1]);
+                rest.fill(T::EQUILIBRIUM);
+            }
+            [] => {}
+        }
+    }
+}
@@ -1,281 +1,907 @@
+use crate::config::Config;
+use crate::dsp::crossfeed::{self, Crossfeed, CrossfeedPreset, CrossfeedSettings};
+use crate::dsp::equalizer::{Equalizer, EqualizerBand, EqualizerPreset, EqualizerSettings};
+use crate::dsp::limiter::{ClippingStats, Limiter, LimiterSettings};
+use crate::dsp::loudness::amplitude_to_db;
+use crate::entry_info::{EntryInfo, EntryResolver};
+use crate::error::{ErrorKind, PlayerError};
+use crate::events::EventSubscriber;
+use crate::library::{Library, LibraryEntry, LibraryQuery, LibraryStatus, MAX_RATING};
+use crate::normalization::{
+    LoudnessAnalyzer, NormalizationMode, NormalizationSettings, Normalizer,
+};
 use crate::openmpt;
-use cpal::traits::{DeviceTrait, HostTrait};
-use cpal::{SampleFormat, SampleRate, SupportedStreamConfigRange};
-use crossbeam::channel::{bounded, Receiver, Sender};
+use crate::output::{self, OutputDevice, OutputSettings, OutputStream, ResolvedDevice};
+use crate::playlist_file::{self, PlaylistEntry, PlaylistImport};
+use crate::preload::{PreloadSettings, Preloader};
+use crate::scan::{ScanJob, ScanMessage, ScanOptions, ScanProgress, ScanReport};
+use crate::scope::{Oscilloscope, ScopeFrame, ScopeSettings, ScopeTap};
+use crate::shuffle::{ShuffleMode, Shuffler};
+use crate::spectrum::{SpectrumAnalyzer, SpectrumFrame, SpectrumSettings, SpectrumTap};
+use crate::transport::{RepeatMode, TransportState};
+use cpal::StreamError;
+use crossbeam::channel::{bounded, select, unbounded, Receiver, Sender};
+use ringbuf::traits::{Observer, Producer};
 use serde::Serialize;
 use std::collections::HashMap;
+use std::path::PathBuf;
 
-use std::sync::{Arc, Mutex};
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Mutex, Weak};
 use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 use uuid::Uuid;
 
-#[derive(Eq, PartialEq, Copy, Clone)]
-enum AudioContextState {
-    Stopped,
-    Playing,
-}
+/// Interval in which default device changes and reappearing devices are
+/// detected
+const DEVICE_POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// Upper limit of the growing delay between attempts to open a device,
+/// which failed to open
+const MAX_DEVICE_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+/// Frames rendered at once into the output ring buffer
+const RENDER_BLOCK_FRAMES: usize = 256;
+/// Interval in which the render thread refills the output ring buffer, if no
+/// commands arrive in between
+const RENDER_INTERVAL: Duration = Duration::from_millis(5);
 
 struct AudioContext {
     playlist: Playlist,
+    entry_resolver: EntryResolver,
+    preloader: Preloader,
     module: Option<openmpt::module::Module>,
     module_duration: Option<f64>,
     module_position: Option<f64>,
     module_metadata: Option<Arc<Vec<openmpt::module::Metadata>>>,
+    module_filepath: Option<String>,
     event_sender: Sender<PlayerEvent>,
-    state: AudioContextState,
+    state: TransportState,
     samples_since_last_position_update: usize,
+    normalizer: Normalizer,
+    equalizer: Equalizer,
+    crossfeed: Crossfeed,
+    limiter: Limiter,
+    clipping: ClippingStats,
+    reported_clipped_samples: u64,
+    spectrum_tap: SpectrumTap,
+    scope_tap: ScopeTap,
+    /// Folder scans, whose files are added to the playlist as they arrive
+    scans: Vec<PendingScan>,
+}
+
+/// Where the files of a folder scan end up in the playlist
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+enum ScanTarget {
+    Replace,
+    Append,
+    InsertNext,
+}
+
+struct PendingScan {
+    job: ScanJob,
+    target: ScanTarget,
+    /// Whether any files have been received yet
+    received: bool,
+    /// Files to insert at once, when the scan has finished
+    pending: Vec<String>,
+}
+
+impl PendingScan {
+    fn new(job: ScanJob, target: ScanTarget) -> Self {
+        Self {
+            job,
+            target,
+            received: false,
+            pending: vec![],
+        }
+    }
 }
 
 impl AudioContext {
-    pub fn new(event_sender: Sender<PlayerEvent>) -> Arc<Mutex<Self>> {
-        Arc::new(Mutex::new(Self {
-            playlist: Playlist::default(),
+    pub fn new(
+        event_sender: Sender<PlayerEvent>,
+        config: &Config,
+        cache_dir: PathBuf,
+        spectrum_tap: SpectrumTap,
+        scope_tap: ScopeTap,
+        entry_resolver: EntryResolver,
+    ) -> Self {
+        Self {
+            entry_resolver,
+            playlist: Playlist::new(config.shuffle, config.repeat),
+            preloader: Preloader::spawn(config.preload),
             module: None,
             module_duration: None,
             module_position: None,
             module_metadata: None,
+            module_filepath: None,
             event_sender,
-            state: AudioContextState::Stopped,
+            state: TransportState::Idle,
             samples_since_last_position_update: 0,
-        }))
+            normalizer: Normalizer::new(
+                config.normalization,
+                LoudnessAnalyzer::spawn(cache_dir.join("loudness.json")),
+            ),
+            equalizer: Equalizer::new(config.equalizer.enabled, config.equalizer.bands.clone()),
+            crossfeed: Crossfeed::new(config.crossfeed),
+            limiter: Limiter::new(config.limiter),
+            clipping: ClippingStats::default(),
+            reported_clipped_samples: 0,
+            spectrum_tap,
+            scope_tap,
+            scans: vec![],
+        }
     }
 }
 
 impl AudioContext {
-    fn desired_config(cfg: &SupportedStreamConfigRange) -> bool {
-        cfg.channels() == 2
-            && cfg.sample_format() == SampleFormat::F32
-            && cfg.max_sample_rate() >= SampleRate(48_000)
-    }
-
-    fn create_cpal_stream(context: Arc<Mutex<AudioContext>>) -> cpal::Stream {
-        // @TODO: Allow user configuration of properties
-        let cpal_host = cpal::default_host();
-        let cpal_device = cpal_host.default_output_device().unwrap();
-        let mut supported_cfgs = cpal_device.supported_output_configs().unwrap();
-        let Some(cfg) = supported_cfgs.find(AudioContext::desired_config) else {
-            panic!("Output device doesn't support desired parameters");
-        };
-        let cfg = cfg.with_sample_rate(SampleRate(48_000)).config();
-
-        cpal_device
-            .build_output_stream(
-                &cfg,
-                move |data: &mut [f32], _cpal| {
-                    context.lock().unwrap().read(cfg.sample_rate.0 as _, data);
-                },
-                |err| {
-                    dbg!(err);
-                },
-                None,
-            )
-            .unwrap()
-    }
-
-    fn read(&mut self, rate: i32, data: &mut [f32]) {
-        if self.state == AudioContextState::Stopped {
-            data.fill(0.0);
-            return;
-        }
-
-        // Nothing is currently playing and no module is loaded (paused)
-        if self.module.is_none() {
-            if !self.activate_current() {
-                self.stop();
-                return;
+    /// Render the next block. `queued_frames` have been rendered before, but
+    /// are still waiting to be played by the output.
+    fn read(&mut self, rate: i32, queued_frames: usize, data: &mut [f32]) {
+        // If a module ends within the block, the rest of it is filled by the
+        // following one. Every part runs through the DSP chain separately, as
+        // the normalizer gain belongs to the module.
+        let mut offset = 0;
+        let mut ended = false;
+        while offset < data.len() && self.state == TransportState::Playing {
+            let Some(ref mut module) = self.module else {
+                break;
+            };
+
+            let frames = module.read_frames(rate, &mut data[offset..]);
+            if frames == 0 {
+                // Don't spin on a following module, which is empty as well
+                if ended {
+                    break;
+                }
+                ended = true;
+                self.module_ended();
+                continue;
             }
+
+            ended = false;
+            let position = module.get_position_seconds();
+            let end = offset + frames * 2;
+            self.process(rate, position, &mut data[offset..end]);
+            offset = end;
         }
+        data[offset..].fill(0.0);
 
-        // We reached the end of the current module. Go to the next one
-        if !self.module.as_mut().unwrap().read(rate, data) {
-            if !self.activate_next() {
-                self.stop();
-                return;
-            }
+        if self.state != TransportState::Playing {
+            return;
         }
+        let Some(ref module) = self.module else {
+            return;
+        };
+        // The block is heard after everything queued before it, so report the
+        // position, which is audible right now
+        let latency = (queued_frames + data.len() / 2) as f64 / rate as f64;
+        let position = (module.get_position_seconds() - latency).max(0.0);
 
         // Send updates limited to once every half second
         self.samples_since_last_position_update += data.len() / 2;
-        if self.samples_since_last_position_update / (48_000 as usize / 2) >= 1
+        if self.samples_since_last_position_update >= rate as usize / 2
             || self.samples_since_last_position_update == 0
         {
             self.samples_since_last_position_update = 0;
-            self.event_sender
-                .send(PlayerEvent::PositionUpdated {
-                    position: self.module.as_ref().unwrap().get_position_seconds(),
-                    duration: self.module_duration.unwrap(),
-                })
-                .unwrap();
+            self.emit(PlayerEvent::PositionUpdated {
+                position,
+                duration: self.module_duration.unwrap_or_default(),
+            });
+
+            if self.clipping.clipped_samples > self.reported_clipped_samples {
+                self.report_clipping();
+            }
+        }
+    }
+
+    /// Run rendered audio of the current module through the DSP chain and
+    /// hand it to the analyzers
+    fn process(&mut self, rate: i32, position: f64, data: &mut [f32]) {
+        self.normalizer.process(data);
+        self.equalizer.process(rate as u32, data);
+        self.crossfeed.process(rate as u32, data);
+        self.clipping.measure(data);
+        self.limiter.process(rate as u32, data);
+        self.spectrum_tap.push(rate as u32, data);
+        self.scope_tap.push(rate as u32, position, data);
+    }
+
+    fn report_clipping(&mut self) {
+        let Some(ref filepath) = self.module_filepath else {
+            return;
+        };
+
+        self.reported_clipped_samples = self.clipping.clipped_samples;
+        let event = PlayerEvent::Diagnostics {
+            filepath: filepath.clone(),
+            clipped_samples: self.clipping.clipped_samples,
+            total_samples: self.clipping.total_samples,
+            peak: amplitude_to_db(self.clipping.peak as f64),
+        };
+        self.emit(event);
+    }
+
+    /// Events can only fail to be sent while shutting down, so this never
+    /// fails.
+    fn emit(&self, event: PlayerEvent) {
+        let _ = self.event_sender.send(event);
+    }
+
+    fn report(&self, error: PlayerError) {
+        eprintln!("Error: {}", error);
+        self.emit(PlayerEvent::from(error));
+    }
+
+    fn report_playlist_errors(&mut self) {
+        for error in std::mem::take(&mut self.playlist.errors) {
+            self.report(error);
+        }
+    }
+
+    /// Send the final clipping statistics of the module, which is about to
+    /// be replaced or unloaded.
+    fn finish_diagnostics(&mut self) {
+        if self.clipping.total_samples > 0 {
+            self.report_clipping();
         }
+        self.clipping = ClippingStats::default();
+        self.reported_clipped_samples = 0;
+        self.module_filepath = None;
     }
 
     fn activate_module(&mut self, module: openmpt::module::Module) {
+        self.finish_diagnostics();
+        let duration = module.get_duration_seconds();
+        let metadata = module.get_metadata();
         self.module = Some(module);
-        self.module_duration = Some(self.module.as_ref().unwrap().get_duration_seconds());
+        self.module_duration = Some(duration);
         self.module_position = Some(0.0);
-        self.module_metadata = Some(self.module.as_ref().unwrap().get_metadata());
+        self.module_metadata = Some(metadata.clone());
 
         let filepath = self.playlist.files[self.playlist.current_index].clone();
+        self.normalizer.set_track(&filepath);
+        self.module_filepath = Some(filepath.clone());
+        self.scope_tap.module_changed(Some(&filepath));
+        self.preloader.preload(self.playlist.neighbours());
         let filename = std::path::Path::new(&filepath)
             .file_name()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .to_string();
-
-        self.event_sender
-            .send(PlayerEvent::Loaded {
-                filepath,
-                filename,
-                metadata: self.module_metadata.as_ref().unwrap().to_vec(),
-                duration: self.module_duration.unwrap(),
-            })
-            .unwrap();
+            .map_or(filepath.clone(), |filename| {
+                filename.to_string_lossy().to_string()
+            });
+
+        self.emit(PlayerEvent::Loaded {
+            filepath,
+            filename,
+            metadata: metadata.to_vec(),
+            duration,
+        });
+    }
+
+    /// The single place, where the transport state changes and the
+    /// corresponding event is sent.
+    fn transition(&mut self, state: TransportState) {
+        if self.state.transition(state) {
+            self.emit(PlayerEvent::StateChanged { state });
+        }
+    }
+
+    fn unload(&mut self) {
+        self.finish_diagnostics();
+        self.scope_tap.module_changed(None);
+        self.module = None;
+        self.module_duration = None;
+        self.module_position = None;
+        self.module_metadata = None;
+    }
+
+    /// Open a module from the playlist using the given navigation and start
+    /// playing it.
+    fn start(
+        &mut self,
+        open: impl FnOnce(&mut Playlist, &mut Preloader) -> Option<openmpt::module::Module>,
+    ) {
+        if self.try_start(open) {
+            return;
+        }
+
+        if self.playlist.files.is_empty() {
+            self.transition(TransportState::Idle);
+        } else {
+            self.transition(TransportState::Ended);
+        }
     }
 
-    fn activate_current(&mut self) -> bool {
-        match self.playlist.current() {
+    /// Like `start`, but stays in `Loading` if nothing could be opened.
+    /// Returns true, if playback has started.
+    fn try_start(
+        &mut self,
+        open: impl FnOnce(&mut Playlist, &mut Preloader) -> Option<openmpt::module::Module>,
+    ) -> bool {
+        self.transition(TransportState::Loading);
+        self.unload();
+
+        let module = open(&mut self.playlist, &mut self.preloader);
+        self.report_playlist_errors();
+        match module {
             Some(module) => {
                 self.activate_module(module);
+                self.transition(TransportState::Playing);
                 true
             }
             None => false,
         }
     }
 
-    fn activate_next(&mut self) -> bool {
-        match self.playlist.next() {
-            Some(module) => {
-                self.activate_module(module);
-                true
+    fn module_ended(&mut self) {
+        if !self.playlist.continues_after_end() {
+            self.unload();
+            self.transition(TransportState::Stopped);
+            return;
+        }
+
+        self.start(Playlist::following);
+    }
+
+    /// Replace the playlist with the given files and folders. Folders are
+    /// scanned in the background and playback starts with the first playable
+    /// module found. Any scan still running is cancelled.
+    pub fn load(&mut self, paths: Vec<String>, options: &ScanOptions) {
+        self.transition(TransportState::Loading);
+        self.unload();
+        self.scans.clear();
+        self.playlist.clear();
+        self.playlist_changed();
+        self.scans.push(PendingScan::new(
+            ScanJob::spawn(paths, options.clone()),
+            ScanTarget::Replace,
+        ));
+    }
+
+    /// Replace the playlist with the given files, e.g. from a playlist file
+    pub fn load_files(&mut self, files: Vec<String>) {
+        self.transition(TransportState::Loading);
+        self.unload();
+        self.scans.clear();
+        self.playlist.load_files(files);
+        self.playlist_loaded();
+    }
+
+    /// Handle the messages of all running folder scans
+    fn poll_scans(&mut self) {
+        let mut index = 0;
+        while index < self.scans.len() {
+            let mut finished = None;
+            for message in self.scans[index].job.messages() {
+                match message {
+                    ScanMessage::Files(files) => self.scan_files(index, files),
+                    ScanMessage::Progress(progress) => {
+                        self.emit(PlayerEvent::ScanProgress(progress))
+                    }
+                    ScanMessage::Finished(report) => finished = Some(report),
+                }
+            }
+
+            match finished {
+                Some(report) => {
+                    let scan = self.scans.remove(index);
+                    self.scan_finished(scan, report);
+                }
+                None => index += 1,
             }
-            None => false,
         }
     }
 
-    fn activate_previous(&mut self) -> bool {
-        match self.playlist.previous() {
-            Some(module) => {
-                self.activate_module(module);
-                true
+    /// Add the next batch of a scan. Only the new entries are announced, the
+    /// whole playlist is sent once the scan has finished.
+    fn scan_files(&mut self, index: usize, files: Vec<String>) {
+        let scan = &mut self.scans[index];
+        let first = !scan.received;
+        scan.received = true;
+        let mut appended_at = self.playlist.files.len();
+        match scan.target {
+            ScanTarget::Replace => {
+                if first {
+                    self.playlist.load_files(files);
+                    appended_at = 0;
+                } else {
+                    self.playlist.append(files);
+                }
+
+                // Start with the first playable module, while the scan goes on
+                if self.state == TransportState::Loading {
+                    let open: fn(&mut Playlist, &mut Preloader) -> Option<openmpt::module::Module> =
+                        if first {
+                            Playlist::current
+                        } else {
+                            Playlist::next
+                        };
+                    self.try_start(open);
+                }
             }
-            None => false,
+            ScanTarget::Append => self.playlist.append(files),
+            // Inserted at once, so the entries keep their order
+            ScanTarget::InsertNext => {
+                scan.pending.extend(files);
+                return;
+            }
+        }
+        self.entries_appended(appended_at);
+    }
+
+    fn scan_finished(&mut self, scan: PendingScan, report: ScanReport) {
+        match scan.target {
+            ScanTarget::Replace => {
+                if self.state == TransportState::Loading {
+                    if self.playlist.files.is_empty() {
+                        self.transition(TransportState::Idle);
+                    } else {
+                        self.transition(TransportState::Ended);
+                    }
+                }
+                self.playlist.dump();
+            }
+            ScanTarget::Append => {}
+            ScanTarget::InsertNext => self.playlist.insert_next(scan.pending),
         }
+
+        for error in report.errors.iter() {
+            self.report(error.clone());
+        }
+        self.report_scan(report);
+        self.playlist_changed();
     }
 
-    pub fn load(&mut self, filepath: &str) {
-        self.stop();
-        self.playlist
-            .load_directory(filepath, PlaylistReadMode::RECURSIVE);
+    fn playlist_loaded(&mut self) {
+        self.playlist_changed();
         self.playlist.dump();
-        self.play();
+        self.start(Playlist::current);
+    }
+
+    fn report_scan(&self, report: ScanReport) {
+        println!(
+            "Scanned {} files in {} folders, {} errors",
+            report.files,
+            report.directories,
+            report.errors.len()
+        );
+        self.emit(PlayerEvent::ScanCompleted(report));
+    }
+
+    fn playlist_changed(&mut self) {
+        self.report_playlist_errors();
+        self.normalizer.set_playlist(&self.playlist.files);
+        if self.module.is_some() {
+            self.preloader.preload(self.playlist.neighbours());
+        }
+        self.emit(PlayerEvent::PlaylistChanged {
+            files: self.playlist.files.clone(),
+            current_index: self.current_entry(),
+        });
+        self.entry_resolver.resolve(self.playlist.files.clone());
+    }
+
+    /// Announce the entries from the given index on, which have been added
+    /// to the end of the playlist
+    fn entries_appended(&mut self, index: usize) {
+        let files = self.playlist.files[index..].to_vec();
+        self.report_playlist_errors();
+        self.normalizer.append_playlist(&files);
+        if self.module.is_some() {
+            self.preloader.preload(self.playlist.neighbours());
+        }
+        self.entry_resolver.append(files.clone());
+        self.emit(PlayerEvent::EntriesAppended {
+            index,
+            files,
+            current_index: self.current_entry(),
+        });
+    }
+
+    /// Index of the loaded playlist entry
+    fn current_entry(&self) -> Option<usize> {
+        self.module.as_ref().map(|_| self.playlist.current_index)
+    }
+
+    /// Add files or folders to the end of the playlist
+    pub fn append(&mut self, paths: Vec<String>, options: &ScanOptions) {
+        self.scans.push(PendingScan::new(
+            ScanJob::spawn(paths, options.clone()),
+            ScanTarget::Append,
+        ));
+    }
+
+    /// Add files or folders right after the current entry
+    pub fn insert_next(&mut self, paths: Vec<String>, options: &ScanOptions) {
+        self.scans.push(PendingScan::new(
+            ScanJob::spawn(paths, options.clone()),
+            ScanTarget::InsertNext,
+        ));
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if index >= self.playlist.files.len() {
+            eprintln!("Can not remove playlist entry {}, no such entry", index);
+            return;
+        }
+
+        let removed_current = self.playlist.remove(index);
+        if self.playlist.files.is_empty() {
+            self.clear();
+            return;
+        }
+
+        // Continue with the entry, which took the place of the removed one
+        if removed_current && self.module.is_some() {
+            let paused = self.state == TransportState::Paused;
+            if index < self.playlist.files.len() {
+                self.start(Playlist::current);
+                if paused {
+                    self.pause();
+                }
+            } else {
+                self.unload();
+                self.transition(TransportState::Ended);
+            }
+        }
+        self.playlist_changed();
+    }
+
+    pub fn move_entry(&mut self, from: usize, to: usize) {
+        let length = self.playlist.files.len();
+        if from >= length || to >= length {
+            eprintln!("Can not move playlist entry {} to {}", from, to);
+            return;
+        }
+
+        self.playlist.move_entry(from, to);
+        self.playlist_changed();
+    }
+
+    pub fn clear(&mut self) {
+        self.unload();
+        self.scans.clear();
+        self.playlist.clear();
+        self.transition(TransportState::Idle);
+        self.playlist_changed();
+    }
+
+    /// Play the entry at the given index
+    pub fn jump(&mut self, index: usize) {
+        if index >= self.playlist.files.len() {
+            eprintln!("Can not jump to playlist entry {}, no such entry", index);
+            return;
+        }
+
+        self.start(|playlist, preloader| playlist.jump(index, preloader));
+        self.playlist_changed();
     }
 
     pub fn play(&mut self) {
-        self.state = AudioContextState::Playing;
+        match self.state {
+            TransportState::Paused => self.transition(TransportState::Playing),
+            TransportState::Stopped => self.start(Playlist::current),
+            // Files may have been added to an empty playlist
+            TransportState::Ended | TransportState::Idle if !self.playlist.files.is_empty() => {
+                self.playlist.rewind();
+                self.start(Playlist::current);
+            }
+            TransportState::Ended
+            | TransportState::Idle
+            | TransportState::Loading
+            | TransportState::Playing => {}
+        }
     }
 
     pub fn pause(&mut self) {
-        if self.state == AudioContextState::Playing {
-            self.state = AudioContextState::Stopped;
+        if self.state == TransportState::Playing {
+            self.transition(TransportState::Paused);
         }
     }
 
     pub fn stop(&mut self) {
-        if self.state == AudioContextState::Playing {
-            self.state = AudioContextState::Stopped;
+        if matches!(self.state, TransportState::Playing | TransportState::Paused) {
+            self.unload();
+            self.transition(TransportState::Stopped);
         }
-        self.module = None;
-        self.module_duration = None;
-        self.module_position = None;
-        self.module_metadata = None;
     }
 
     pub fn previous(&mut self) {
-        self.stop();
-        self.activate_previous();
-        self.play();
+        if self.playlist.files.is_empty() {
+            return;
+        }
+
+        // Restart the first module, if there is no previous one
+        self.start(|playlist, preloader| {
+            playlist
+                .previous(preloader)
+                .or_else(|| playlist.current(preloader))
+        });
     }
 
     pub fn next(&mut self) {
-        self.stop();
-        self.activate_next();
-        self.play();
+        if self.playlist.files.is_empty() {
+            return;
+        }
+
+        self.start(Playlist::next_or_wrap);
+    }
+
+    pub fn seek(&mut self, position: f64) -> Result<(), PlayerError> {
+        let Some(ref mut module) = self.module else {
+            return Err(PlayerError::new(
+                ErrorKind::Playback,
+                "No module loaded to seek in",
+            ));
+        };
+
+        module.set_position_seconds(position);
+        self.emit(PlayerEvent::Seeked {
+            position,
+            duration: self.module_duration.unwrap_or_default(),
+        });
+        Ok(())
+    }
+
+    pub fn configure_equalizer(&mut self, enabled: bool, bands: Vec<EqualizerBand>) {
+        self.equalizer.configure(enabled, bands);
+    }
+
+    pub fn configure_crossfeed(&mut self, settings: CrossfeedSettings) {
+        self.crossfeed.configure(settings);
+    }
+
+    pub fn configure_normalization(&mut self, settings: NormalizationSettings) {
+        self.normalizer.configure(settings);
+    }
+
+    pub fn configure_limiter(&mut self, settings: LimiterSettings) {
+        self.limiter.configure(settings);
+    }
+
+    pub fn configure_preload(&mut self, settings: PreloadSettings) {
+        self.preloader.configure(settings);
     }
 
-    pub fn seek(&mut self, position: f64) {
+    pub fn set_shuffle_mode(&mut self, mode: ShuffleMode) {
+        self.playlist.shuffle.set_mode(mode);
         if self.module.is_some() {
-            self.module.as_mut().unwrap().set_position_seconds(position);
+            self.preloader.preload(self.playlist.neighbours());
+        }
+        self.report_playback_modes();
+    }
+
+    pub fn set_ratings(&mut self, ratings: HashMap<String, u8>) {
+        self.playlist.shuffle.set_ratings(ratings);
+    }
+
+    pub fn set_rating(&mut self, filepath: &str, rating: Option<u8>) {
+        self.playlist.shuffle.set_rating(filepath, rating);
+    }
+
+    pub fn set_repeat_mode(&mut self, mode: RepeatMode) {
+        self.playlist.repeat = mode;
+        self.report_playback_modes();
+    }
+
+    fn report_playback_modes(&self) {
+        self.emit(PlayerEvent::PlaybackModesChanged {
+            shuffle: self.playlist.shuffle.mode(),
+            repeat: self.playlist.repeat,
+        });
+    }
+
+    /// Bring the context back into a consistent state after the playback
+    /// thread crashed.
+    fn recover(&mut self) {
+        self.unload();
+        self.transition(TransportState::Loading);
+        if self.playlist.files.is_empty() {
+            self.transition(TransportState::Idle);
+        } else {
+            self.transition(TransportState::Stopped);
+        }
+    }
+
+    fn playback_state(&self) -> PlaybackState {
+        let filename = self.module_filepath.as_ref().and_then(|filepath| {
+            std::path::Path::new(filepath)
+                .file_name()
+                .map(|filename| filename.to_string_lossy().to_string())
+        });
+
+        PlaybackState {
+            transport: self.state,
+            filepath: self.module_filepath.clone(),
+            filename,
+            metadata: self
+                .module_metadata
+                .as_ref()
+                .map_or(vec![], |metadata| metadata.to_vec()),
+            position: self
+                .module
+                .as_ref()
+                .map(|module| module.get_position_seconds()),
+            duration: self.module_duration,
+            playlist_index: self.current_entry(),
+            playlist_length: self.playlist.files.len(),
         }
-        self.event_sender
-            .send(PlayerEvent::Seeked {
-                position,
-                duration: self.module_duration.unwrap(),
-            })
-            .unwrap();
     }
 }
 
 struct Playlist {
     files: Vec<String>,
     current_index: usize,
-}
-
-#[derive(Eq, PartialEq, Copy, Clone)]
-enum PlaylistReadMode {
-    FLAT,
-    RECURSIVE,
+    shuffle: Shuffler,
+    repeat: RepeatMode,
+    /// Indices of the previously played entries, so `previous` returns to
+    /// them even if shuffled
+    history: Vec<usize>,
+    /// Errors, which occurred while reading files. They are reported by the
+    /// `AudioContext`.
+    errors: Vec<PlayerError>,
 }
 
 impl Playlist {
-    pub fn from_directory(filepath: &str, mode: PlaylistReadMode) -> Self {
-        let files = Self::read_directory(filepath, mode);
+    pub fn new(shuffle: ShuffleMode, repeat: RepeatMode) -> Self {
         Self {
-            files,
+            files: vec![],
             current_index: 0,
+            shuffle: Shuffler::new(shuffle),
+            repeat,
+            history: vec![],
+            errors: vec![],
         }
     }
 
-    pub fn load_directory(&mut self, filepath: &str, mode: PlaylistReadMode) {
-        self.files = Self::read_directory(filepath, mode);
-        self.current_index = 0;
+    pub fn load_files(&mut self, files: Vec<String>) {
+        self.files = files;
+        self.shuffle.reset();
+        self.history.clear();
+        self.current_index = self.shuffle.first(&self.files);
+    }
+
+    /// Apply a change of positions to the current index and the history.
+    /// Entries mapped to `None` have been removed.
+    fn remap(&mut self, map: impl Fn(usize) -> Option<usize>) {
+        self.history = self
+            .history
+            .iter()
+            .filter_map(|index| map(*index))
+            .collect();
+        if let Some(index) = map(self.current_index) {
+            self.current_index = index;
+        }
+        self.shuffle.forget_upcoming();
+    }
+
+    pub fn append(&mut self, mut files: Vec<String>) {
+        self.files.append(&mut files);
+        self.shuffle.forget_upcoming();
+    }
+
+    pub fn insert_next(&mut self, files: Vec<String>) {
+        if self.files.is_empty() {
+            self.files = files;
+            self.shuffle.forget_upcoming();
+            return;
+        }
+
+        self.shuffle.queue_next(&files);
+        let at = (self.current_index + 1).min(self.files.len());
+        let count = files.len();
+        self.files.splice(at..at, files);
+        self.remap(|index| Some(if index >= at { index + count } else { index }));
     }
 
-    fn read_directory(filepath: &str, mode: PlaylistReadMode) -> Vec<String> {
-        let mut files = vec![];
-        for entry in std::fs::read_dir(filepath).unwrap() {
-            let entry = entry.unwrap();
-            let path = entry.path();
-            if path.is_file() {
-                files.push(path.to_str().unwrap().to_string());
-            } else if path.is_dir() && mode == PlaylistReadMode::RECURSIVE {
-                files.append(&mut Self::read_directory(path.to_str().unwrap(), mode));
+    /// Remove the entry at the given index. Returns true, if it has been the
+    /// current one. The current index then points to the entry, which took
+    /// its place.
+    pub fn remove(&mut self, index: usize) -> bool {
+        let removed_current = index == self.current_index;
+        self.files.remove(index);
+        self.remap(|other| match other.cmp(&index) {
+            std::cmp::Ordering::Less => Some(other),
+            std::cmp::Ordering::Equal => None,
+            std::cmp::Ordering::Greater => Some(other - 1),
+        });
+        removed_current
+    }
+
+    pub fn move_entry(&mut self, from: usize, to: usize) {
+        let entry = self.files.remove(from);
+        self.files.insert(to, entry);
+        self.remap(|index| {
+            if index == from {
+                return Some(to);
             }
+            let index = if index > from { index - 1 } else { index };
+            Some(if index >= to { index + 1 } else { index })
+        });
+    }
+
+    pub fn clear(&mut self) {
+        self.files.clear();
+        self.history.clear();
+        self.current_index = 0;
+        self.shuffle.reset();
+    }
+
+    pub fn jump(
+        &mut self,
+        index: usize,
+        preloader: &mut Preloader,
+    ) -> Option<openmpt::module::Module> {
+        if index != self.current_index && self.current_index < self.files.len() {
+            self.history.push(self.current_index);
         }
+        self.current_index = index;
+        self.shuffle.mark_played(&self.files[index]);
+        self.current(preloader)
+    }
 
-        files
+    /// Start over after the end of the playlist has been reached
+    pub fn rewind(&mut self) {
+        self.shuffle.restart();
+        self.history.clear();
+        self.current_index = self.shuffle.first(&self.files);
     }
 
-    fn try_open_module(filepath: &str) -> Option<openmpt::module::Module> {
+    fn try_open_module(
+        &mut self,
+        filepath: &str,
+        preloader: &mut Preloader,
+    ) -> Option<openmpt::module::Module> {
+        if let Some(module) = preloader.take(filepath) {
+            return module;
+        }
+
         // @TODO: Should we limit the maximum filesize here?
-        let data = std::fs::read(filepath).unwrap();
+        let data = match std::fs::read(filepath) {
+            Ok(data) => data,
+            Err(err) => {
+                self.errors
+                    .push(PlayerError::new(ErrorKind::Io, err).with_path(filepath));
+                return None;
+            }
+        };
         match openmpt::module::Module::try_from_memory(&data) {
             Ok(module) => Some(module),
             Err(_) => None,
         }
     }
 
-    pub fn previous(&mut self) -> Option<openmpt::module::Module> {
-        while self.current_index > 0 && self.current_index - 1 < self.files.len() {
+    pub fn previous(&mut self, preloader: &mut Preloader) -> Option<openmpt::module::Module> {
+        while let Some(index) = self.history.pop() {
+            let Some(filepath) = self.files.get(index).cloned() else {
+                continue;
+            };
+            self.current_index = index;
+            eprintln!(
+                "Trying to open previously played file {} of {}: {}",
+                self.current_index + 1,
+                self.files.len(),
+                filepath
+            );
+            if let Some(module) = self.try_open_module(&filepath, preloader) {
+                return Some(module);
+            }
+        }
+
+        // Without shuffling, there is a previous file even without history,
+        // e.g. after jumping into the middle of the playlist.
+        while self.shuffle.mode() == ShuffleMode::Off
+            && self.current_index > 0
+            && self.current_index - 1 < self.files.len()
+        {
             self.current_index -= 1;
-            let filepath = &self.files[self.current_index];
+            let filepath = self.files[self.current_index].clone();
             eprintln!(
                 "Trying to open previous file in queue {} of {}: {}",
                 self.current_index + 1,
                 self.files.len(),
                 filepath
             );
-            if let Some(module) = Self::try_open_module(filepath) {
+            if let Some(module) = self.try_open_module(&filepath, preloader) {
                 return Some(module);
             }
         }
@@ -284,21 +910,21 @@ impl Playlist {
         return None;
     }
 
-    pub fn current(&mut self) -> Option<openmpt::module::Module> {
+    pub fn current(&mut self, preloader: &mut Preloader) -> Option<openmpt::module::Module> {
         if self.current_index < self.files.len() {
-            let filepath = &self.files[self.current_index];
+            let filepath = self.files[self.current_index].clone();
             eprintln!(
                 "Trying to open current file in queue {} of {}: {}",
                 self.current_index + 1,
                 self.files.len(),
                 filepath
             );
-            if let Some(module) = Self::try_open_module(filepath) {
+            if let Some(module) = self.try_open_module(&filepath, preloader) {
                 return Some(module);
             } else {
                 // In case current can't be played (is not a module), try the
                 // next one.
-                return self.next();
+                return self.next(preloader);
             }
         }
 
@@ -306,25 +932,72 @@ impl Playlist {
         return None;
     }
 
-    pub fn next(&mut self) -> Option<openmpt::module::Module> {
-        while self.current_index + 1 < self.files.len() {
-            self.current_index += 1;
-            let filepath = &self.files[self.current_index];
+    pub fn next(&mut self, preloader: &mut Preloader) -> Option<openmpt::module::Module> {
+        let origin = self.current_index;
+        while let Some(index) = self.shuffle.advance(&self.files, self.current_index) {
+            self.current_index = index;
+            let filepath = self.files[self.current_index].clone();
             eprintln!(
                 "Trying to open next file in queue {} of {}: {}",
                 self.current_index + 1,
                 self.files.len(),
                 filepath
             );
-            if let Some(module) = Self::try_open_module(filepath) {
+            if let Some(module) = self.try_open_module(&filepath, preloader) {
+                self.history.push(origin);
                 return Some(module);
             }
+            self.shuffle.mark_unplayable(&filepath);
         }
 
         eprintln!("No more files in queue");
         return None;
     }
 
+    /// Like `next`, but starts over at the end of the playlist, if all
+    /// entries should be repeated.
+    pub fn next_or_wrap(&mut self, preloader: &mut Preloader) -> Option<openmpt::module::Module> {
+        let module = self.next(preloader);
+        if module.is_some() || self.repeat != RepeatMode::All {
+            return module;
+        }
+
+        eprintln!("Repeating playlist");
+        self.rewind();
+        self.current(preloader)
+    }
+
+    /// Whether playback continues, once a module has ended by itself
+    pub fn continues_after_end(&self) -> bool {
+        self.repeat != RepeatMode::StopAfterCurrent
+    }
+
+    /// The module to play after the current one has ended by itself
+    pub fn following(&mut self, preloader: &mut Preloader) -> Option<openmpt::module::Module> {
+        match self.repeat {
+            RepeatMode::One => self.current(preloader),
+            _ => self.next_or_wrap(preloader),
+        }
+    }
+
+    /// Files, which are likely to be played after the current one, ordered
+    /// by priority
+    pub fn neighbours(&mut self) -> Vec<String> {
+        let next = self
+            .shuffle
+            .peek(&self.files, self.current_index)
+            .and_then(|index| self.files.get(index));
+        let previous = match self.history.last() {
+            Some(index) => self.files.get(*index),
+            None if self.shuffle.mode() == ShuffleMode::Off => self
+                .current_index
+                .checked_sub(1)
+                .and_then(|index| self.files.get(index)),
+            None => None,
+        };
+        next.into_iter().chain(previous).cloned().collect()
+    }
+
     pub fn dump(&self) {
         eprintln!("Playlist:");
         for (i, file) in self.files.iter().enumerate() {
@@ -335,25 +1008,84 @@ impl Playlist {
 
 impl Default for Playlist {
     fn default() -> Self {
-        Self {
-            files: vec![],
-            current_index: 0,
-        }
+        Self::new(ShuffleMode::Off, RepeatMode::Off)
+    }
+}
+
+/// State of the playback thread
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaybackState {
+    pub transport: TransportState,
+    pub filepath: Option<String>,
+    pub filename: Option<String>,
+    pub metadata: Vec<openmpt::module::Metadata>,
+    pub position: Option<f64>,
+    pub duration: Option<f64>,
+    pub playlist_index: Option<usize>,
+    pub playlist_length: usize,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaylistContents {
+    pub entries: Vec<EntryInfo>,
+    pub current_index: Option<usize>,
+}
+
+/// Playlist to be saved as file, independent of the player
+pub struct PlaylistExport {
+    files: Vec<String>,
+    entries: EntryResolver,
+}
+
+impl PlaylistExport {
+    /// Save the playlist. The format is chosen by the extension.
+    ///
+    /// Entries, whose metadata hasn't been resolved yet, are loaded here to
+    /// get their titles and durations. This may take a while, so it must not
+    /// be called while holding the lock of the player.
+    pub fn write(&self, filepath: &str) -> Result<(), PlayerError> {
+        let entries: Vec<PlaylistEntry> = self
+            .files
+            .iter()
+            .map(|file| {
+                let info = self.entries.get(file);
+                match info.resolved {
+                    true => info,
+                    false => EntryInfo::read(file),
+                }
+            })
+            .map(PlaylistEntry::from)
+            .collect();
+        playlist_file::export(std::path::Path::new(filepath), &entries)
+            .map_err(|err| PlayerError::new(ErrorKind::Io, err).with_path(filepath))
     }
 }
 
+/// Snapshot of the whole player, which allows late subscribers to catch up
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerState {
+    #[serde(flatten)]
+    pub playback: PlaybackState,
+    pub settings: Config,
+}
+
 #[derive(Serialize, Clone)]
 #[serde(rename_all = "camelCase", tag = "event", content = "data")]
 pub enum PlayerEvent {
+    /// Always sent as the first event to a new subscriber
+    State(Box<PlayerState>),
     Loaded {
         filename: String,
         filepath: String,
         metadata: Vec<openmpt::module::Metadata>,
         duration: f64,
     },
-    Playing,
-    Paused,
-    Stopped,
+    StateChanged {
+        state: TransportState,
+    },
     PositionUpdated {
         position: f64,
         duration: f64,
@@ -362,25 +1094,594 @@ pub enum PlayerEvent {
         position: f64,
         duration: f64,
     },
+    /// Clipping statistics of a module, measured before the limiter. Peak is
+    /// given in dBFS.
+    #[serde(rename_all = "camelCase")]
+    Diagnostics {
+        filepath: String,
+        clipped_samples: u64,
+        total_samples: u64,
+        peak: f64,
+    },
+    /// The output device has been (re)opened. `fallback` is set, if the
+    /// configured device is missing and the default device is used instead.
+    DeviceChanged {
+        host: String,
+        device: String,
+        fallback: bool,
+    },
+    Error {
+        kind: ErrorKind,
+        message: String,
+        path: Option<String>,
+    },
+    /// The playlist has been loaded or edited
+    #[serde(rename_all = "camelCase")]
+    PlaylistChanged {
+        files: Vec<String>,
+        current_index: Option<usize>,
+    },
+    /// Entries have been added to the end of the playlist, starting at
+    /// `index`
+    #[serde(rename_all = "camelCase")]
+    EntriesAppended {
+        index: usize,
+        files: Vec<String>,
+        current_index: Option<usize>,
+    },
+    /// Files and folders have been scanned for the playlist
+    ScanProgress(ScanProgress),
+    ScanCompleted(ScanReport),
+    /// The metadata of a playlist entry has been read
+    PlaylistEntryUpdated(EntryInfo),
+    PlaybackModesChanged {
+        shuffle: ShuffleMode,
+        repeat: RepeatMode,
+    },
+    /// The library database has been updated
+    LibraryChanged(LibraryStatus),
+    /// The output device failed or disappeared. Playback continues on
+    /// another device, which is announced by `DeviceChanged`.
+    DeviceLost {
+        device: String,
+        message: String,
+    },
     Terminated,
 }
 
+/// Reported by the error callback of the output stream, tagged with the
+/// generation of the stream it belongs to.
+struct OutputError {
+    generation: u64,
+    device_lost: bool,
+    message: String,
+}
+
+#[derive(Clone)]
+struct ActiveOutput {
+    host: String,
+    name: String,
+}
+
+/// A device, whose stream could not be opened, and when to try it again
+#[derive(Clone)]
+struct FailedOutput {
+    host: String,
+    name: String,
+    retry_at: Instant,
+    backoff: Duration,
+}
+
+impl FailedOutput {
+    fn is(&self, device: &ResolvedDevice) -> bool {
+        self.host == device.host && self.name == device.name
+    }
+}
+
+/// State of the `Output`, which the device watcher compares the available
+/// devices against
+#[derive(Default)]
+struct WatchedOutput {
+    settings: OutputSettings,
+    active: Option<ActiveOutput>,
+    failed: Option<FailedOutput>,
+    generation: u64,
+}
+
+/// Detect changes of the output device on a separate thread, as enumerating
+/// devices may take longer than the ring buffer of the stream lasts. Changes
+/// are handed to the playback thread as `DeviceChanged` command.
+///
+/// The watcher only holds a weak handle of the command sender, so it doesn't
+/// keep the playback thread alive once the player is gone.
+fn spawn_device_watcher(watched: Arc<Mutex<WatchedOutput>>, sender: Weak<Sender<PlayerCommand>>) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(DEVICE_POLL_INTERVAL);
+
+        let (settings, active, failed, generation) = {
+            let watched = watched.lock().unwrap();
+            (
+                watched.settings.clone(),
+                watched.active.clone(),
+                watched.failed.clone(),
+                watched.generation,
+            )
+        };
+        let Ok(device) = output::resolve(&settings) else {
+            continue;
+        };
+        if failed.is_some_and(|failed| failed.is(&device) && Instant::now() < failed.retry_at) {
+            continue;
+        }
+        let lost = match active {
+            Some(ref active) if active.host == device.host && active.name == device.name => {
+                continue
+            }
+            Some(ref active) => !output::device_exists(&active.host, &active.name),
+            None => false,
+        };
+
+        let command = PlayerCommand::DeviceChanged {
+            device,
+            lost,
+            generation,
+        };
+        let Some(sender) = sender.upgrade() else {
+            break;
+        };
+        // The playback thread is gone
+        if sender.send(command).is_err() {
+            break;
+        }
+    });
+}
+
+/// Output stream of the playback thread, which is rebuilt whenever the
+/// device fails, disappears or the default device changes. The module is
+/// kept inside the `AudioContext`, so playback continues at the same
+/// position.
+struct Output {
+    settings: OutputSettings,
+    stream: Option<OutputStream>,
+    active: Option<ActiveOutput>,
+    failed: Option<FailedOutput>,
+    generation: u64,
+    error_sender: Sender<OutputError>,
+    buffer: Vec<f32>,
+    watched: Arc<Mutex<WatchedOutput>>,
+}
+
+impl Output {
+    fn new(
+        settings: OutputSettings,
+        error_sender: Sender<OutputError>,
+        watched: Arc<Mutex<WatchedOutput>>,
+    ) -> Self {
+        let output = Self {
+            settings,
+            stream: None,
+            active: None,
+            failed: None,
+            generation: 0,
+            error_sender,
+            buffer: vec![0.0; RENDER_BLOCK_FRAMES * 2],
+            watched,
+        };
+        output.publish();
+        output
+    }
+
+    /// Share the current device with the device watcher
+    fn publish(&self) {
+        let mut watched = self.watched.lock().unwrap();
+        watched.settings = self.settings.clone();
+        watched.active = self.active.clone();
+        watched.failed = self.failed.clone();
+        watched.generation = self.generation;
+    }
+
+    /// Open the output device described by the settings, falling back to the
+    /// default device if it is not available.
+    fn open(&mut self, event_sender: &Sender<PlayerEvent>) {
+        match output::resolve(&self.settings) {
+            Ok(resolved) => self.start(resolved, event_sender),
+            Err(err) => {
+                let error = PlayerError::new(ErrorKind::Output, err);
+                eprintln!("Could not open output device: {}", error);
+                let _ = event_sender.send(error.into());
+                self.close();
+            }
+        }
+    }
+
+    fn close(&mut self) {
+        self.stream = None;
+        self.active = None;
+        self.generation += 1;
+        self.publish();
+    }
+
+    fn start(&mut self, resolved: ResolvedDevice, event_sender: &Sender<PlayerEvent>) {
+        // Release the current device first, as some backends can't open the
+        // same device twice.
+        self.close();
+
+        if resolved.fallback {
+            eprintln!(
+                "Output device {:?} is not available, falling back to {}",
+                self.settings.device, resolved.name
+            );
+        }
+        println!("Using output device {} ({})", resolved.name, resolved.host);
+
+        let generation = self.generation;
+        let error_sender = self.error_sender.clone();
+        let on_error = move |err: StreamError| {
+            let _ = error_sender.send(OutputError {
+                generation,
+                device_lost: matches!(err, StreamError::DeviceNotAvailable),
+                message: err.to_string(),
+            });
+        };
+
+        match output::open_stream(&resolved.device, on_error) {
+            Ok(stream) => self.stream = Some(stream),
+            Err(err) => {
+                self.open_failed(resolved, err, event_sender);
+                return;
+            }
+        }
+
+        let _ = event_sender.send(PlayerEvent::DeviceChanged {
+            host: resolved.host.clone(),
+            device: resolved.name.clone(),
+            fallback: resolved.fallback,
+        });
+        self.active = Some(ActiveOutput {
+            host: resolved.host,
+            name: resolved.name,
+        });
+        self.failed = None;
+        self.publish();
+    }
+
+    /// Remember a device, which could not be opened. The watcher retries it
+    /// with a growing delay, while the error is only reported once.
+    fn open_failed(
+        &mut self,
+        resolved: ResolvedDevice,
+        err: anyhow::Error,
+        event_sender: &Sender<PlayerEvent>,
+    ) {
+        let error = PlayerError::new(ErrorKind::Output, err).with_path(resolved.name.clone());
+        eprintln!("Could not create output stream: {}", error);
+
+        let backoff = match self.failed {
+            Some(ref failed) if failed.is(&resolved) => {
+                (failed.backoff * 2).min(MAX_DEVICE_RETRY_INTERVAL)
+            }
+            _ => {
+                let _ = event_sender.send(error.into());
+                DEVICE_POLL_INTERVAL
+            }
+        };
+        self.failed = Some(FailedOutput {
+            host: resolved.host,
+            name: resolved.name,
+            retry_at: Instant::now() + backoff,
+            backoff,
+        });
+        self.publish();
+    }
+
+    fn configure(&mut self, settings: OutputSettings, event_sender: &Sender<PlayerEvent>) {
+        let device_changed =
+            settings.host != self.settings.host || settings.device != self.settings.device;
+        self.settings = settings;
+        self.publish();
+        if device_changed {
+            self.open(event_sender);
+        }
+    }
+
+    fn handle_error(
+        &mut self,
+        error: OutputError,
+        context: &mut AudioContext,
+        event_sender: &Sender<PlayerEvent>,
+    ) {
+        // Errors of already replaced streams
+        if error.generation != self.generation {
+            return;
+        }
+
+        // Backend specific errors, like underruns, don't stop the stream
+        if !error.device_lost {
+            eprintln!("Output stream error: {}", error.message);
+            return;
+        }
+
+        eprintln!("Output stream failed: {}", error.message);
+        self.device_lost(error.message, context, event_sender);
+        self.open(event_sender);
+    }
+
+    /// Switch to the device found by the device watcher. This follows the
+    /// default device, switches back to a preferred device once it reappears
+    /// and retries after all devices have been gone.
+    fn device_changed(
+        &mut self,
+        device: ResolvedDevice,
+        lost: bool,
+        generation: u64,
+        context: &mut AudioContext,
+        event_sender: &Sender<PlayerEvent>,
+    ) {
+        // The output has changed since the watcher looked at it
+        if generation != self.generation {
+            return;
+        }
+
+        if lost {
+            self.device_lost(
+                String::from("Output device disappeared"),
+                context,
+                event_sender,
+            );
+        }
+
+        println!("Output device changed to {}", device.name);
+        self.start(device, event_sender);
+    }
+
+    fn device_lost(
+        &mut self,
+        message: String,
+        context: &mut AudioContext,
+        event_sender: &Sender<PlayerEvent>,
+    ) {
+        let device = self
+            .active
+            .take()
+            .map(|active| active.name)
+            .unwrap_or_default();
+        self.close();
+        let _ = event_sender.send(PlayerEvent::DeviceLost { device, message });
+
+        if self.settings.pause_on_device_loss {
+            context.pause();
+        }
+    }
+
+    /// Render blocks of audio, until the ring buffer of the stream is full
+    fn render(&mut self, context: &mut AudioContext) {
+        let Some(ref mut stream) = self.stream else {
+            return;
+        };
+
+        while stream.producer.vacant_len() >= self.buffer.len() {
+            let queued_frames = stream.producer.occupied_len() / 2;
+            context.read(stream.sample_rate as i32, queued_frames, &mut self.buffer);
+            stream.producer.push_slice(&self.buffer);
+        }
+    }
+}
+
+impl From<PlayerError> for PlayerEvent {
+    fn from(error: PlayerError) -> Self {
+        PlayerEvent::Error {
+            kind: error.kind,
+            message: error.message,
+            path: error.path,
+        }
+    }
+}
+
 enum PlayerCommand {
-    Load(String),
+    Load(Vec<String>, ScanOptions),
     Play,
     Pause,
     Stop,
     Previous,
     Next,
-    Seek(f64),
+    Seek(f64, Sender<Result<(), PlayerError>>),
+    ConfigureEqualizer {
+        enabled: bool,
+        bands: Vec<EqualizerBand>,
+    },
+    ConfigureCrossfeed(CrossfeedSettings),
+    ConfigureNormalization(NormalizationSettings),
+    ConfigureLimiter(LimiterSettings),
+    ConfigurePreload(PreloadSettings),
+    SetShuffleMode(ShuffleMode),
+    SetRepeatMode(RepeatMode),
+    /// Ratings of all rated modules for weighted shuffling
+    SetRatings(HashMap<String, u8>),
+    SetRating(String, Option<u8>),
+    LoadFiles(Vec<String>),
+    Append(Vec<String>, ScanOptions),
+    InsertNext(Vec<String>, ScanOptions),
+    Remove(usize),
+    Move {
+        from: usize,
+        to: usize,
+    },
+    Clear,
+    Jump(usize),
+    GetState(Sender<PlaybackState>),
+    /// Reply with the files and the index of the loaded entry
+    GetPlaylist(Sender<(Vec<String>, Option<usize>)>),
+    ConfigureOutput(OutputSettings),
+    /// The device watcher found another device to be used. `lost` tells,
+    /// whether the active one has disappeared.
+    DeviceChanged {
+        device: ResolvedDevice,
+        lost: bool,
+        generation: u64,
+    },
     Terminate,
 }
 
+/// Render audio and handle commands, until the player is terminated
+fn playback_loop(
+    receiver: &Receiver<PlayerCommand>,
+    error_receiver: &Receiver<OutputError>,
+    audio_context: &mut AudioContext,
+    output: &mut Output,
+    event_sender: &Sender<PlayerEvent>,
+) {
+    // The playback thread renders the audio ahead of time, so that the
+    // output callback never has to wait for commands, file IO or locks.
+    'receive_loop: loop {
+        output.render(audio_context);
+        audio_context.poll_scans();
+
+        let command = select! {
+            recv(receiver) -> command => match command {
+                Ok(command) => command,
+                // The player is gone
+                Err(_) => break 'receive_loop,
+            },
+            recv(error_receiver) -> error => {
+                if let Ok(error) = error {
+                    output.handle_error(error, audio_context, event_sender);
+                }
+                continue 'receive_loop;
+            },
+            default(RENDER_INTERVAL) => continue 'receive_loop,
+        };
+
+        match command {
+            PlayerCommand::Load(paths, options) => {
+                println!("Load {}", paths.join(", "));
+                audio_context.load(paths, &options);
+            }
+
+            PlayerCommand::Play => {
+                println!("Play");
+                audio_context.play();
+            }
+            PlayerCommand::Pause => {
+                println!("Pause");
+                audio_context.pause();
+            }
+            PlayerCommand::Stop => {
+                println!("Stop");
+                audio_context.stop();
+            }
+            PlayerCommand::Previous => {
+                println!("Previous");
+                audio_context.previous();
+            }
+            PlayerCommand::Next => {
+                println!("Next");
+                audio_context.next();
+            }
+            PlayerCommand::LoadFiles(files) => {
+                println!("Load {} files", files.len());
+                audio_context.load_files(files);
+            }
+            PlayerCommand::Append(paths, options) => {
+                println!("Append {} entries", paths.len());
+                audio_context.append(paths, &options);
+            }
+            PlayerCommand::InsertNext(paths, options) => {
+                println!("Insert {} entries next", paths.len());
+                audio_context.insert_next(paths, &options);
+            }
+            PlayerCommand::Remove(index) => {
+                println!("Remove entry {}", index);
+                audio_context.remove(index);
+            }
+            PlayerCommand::Move { from, to } => {
+                println!("Move entry {} to {}", from, to);
+                audio_context.move_entry(from, to);
+            }
+            PlayerCommand::Clear => {
+                println!("Clear playlist");
+                audio_context.clear();
+            }
+            PlayerCommand::Jump(index) => {
+                println!("Jump to entry {}", index);
+                audio_context.jump(index);
+            }
+            PlayerCommand::GetState(reply) => {
+                let _ = reply.send(audio_context.playback_state());
+            }
+            PlayerCommand::GetPlaylist(reply) => {
+                let _ = reply.send((
+                    audio_context.playlist.files.clone(),
+                    audio_context.current_entry(),
+                ));
+            }
+            PlayerCommand::Terminate => {
+                let _ = event_sender.send(PlayerEvent::Terminated);
+                break 'receive_loop;
+            }
+            PlayerCommand::Seek(position, reply) => {
+                println!("Seek: {}", position);
+                let _ = reply.send(audio_context.seek(position));
+            }
+            PlayerCommand::ConfigureEqualizer { enabled, bands } => {
+                println!("Configure equalizer: enabled={}", enabled);
+                audio_context.configure_equalizer(enabled, bands);
+            }
+            PlayerCommand::ConfigureCrossfeed(settings) => {
+                println!("Configure crossfeed: enabled={}", settings.enabled);
+                audio_context.configure_crossfeed(settings);
+            }
+            PlayerCommand::ConfigureNormalization(settings) => {
+                println!("Configure normalization: mode={:?}", settings.mode);
+                audio_context.configure_normalization(settings);
+            }
+            PlayerCommand::ConfigureLimiter(settings) => {
+                println!("Configure limiter: enabled={}", settings.enabled);
+                audio_context.configure_limiter(settings);
+            }
+            PlayerCommand::ConfigurePreload(settings) => {
+                println!("Configure preload: budget={}MiB", settings.memory_budget);
+                audio_context.configure_preload(settings);
+            }
+            PlayerCommand::SetShuffleMode(mode) => {
+                println!("Set shuffle mode: {:?}", mode);
+                audio_context.set_shuffle_mode(mode);
+            }
+            PlayerCommand::SetRepeatMode(mode) => {
+                println!("Set repeat mode: {:?}", mode);
+                audio_context.set_repeat_mode(mode);
+            }
+            PlayerCommand::SetRatings(ratings) => {
+                println!("Set {} ratings", ratings.len());
+                audio_context.set_ratings(ratings);
+            }
+            PlayerCommand::SetRating(filepath, rating) => {
+                println!("Rate {}: {:?}", filepath, rating);
+                audio_context.set_rating(&filepath, rating);
+            }
+            PlayerCommand::ConfigureOutput(settings) => {
+                println!("Configure output: {:?}", settings);
+                output.configure(settings, event_sender);
+            }
+            PlayerCommand::DeviceChanged {
+                device,
+                lost,
+                generation,
+            } => output.device_changed(device, lost, generation, audio_context, event_sender),
+        }
+    }
+}
+
 pub struct Player {
-    playback_sender: Option<Sender<PlayerCommand>>,
+    playback_sender: Option<Arc<Sender<PlayerCommand>>>,
     playback_join_handle: Option<JoinHandle<()>>,
     events_join_handle: Option<JoinHandle<()>>,
-    subscribers: Arc<Mutex<HashMap<String, tauri::ipc::Channel<PlayerEvent>>>>,
+    subscribers: Arc<Mutex<HashMap<String, EventSubscriber>>>,
+    spectrum: SpectrumAnalyzer,
+    scope: Oscilloscope,
+    entries: EntryResolver,
+    /// The error, if the library database could not be opened
+    library: Result<Library, PlayerError>,
+    config: Config,
 }
 
 impl Drop for Player {
@@ -390,164 +1691,607 @@ impl Drop for Player {
 }
 
 impl Player {
-    pub fn spawn() -> Self {
+    pub fn spawn(config: Config, cache_dir: PathBuf, data_dir: PathBuf) -> Self {
+        // Sending events must never block the playback thread
+        let (sender, receiver) = unbounded::<PlayerEvent>();
+
+        let (scope, scope_tap) = Oscilloscope::spawn(config.scope);
         let mut player = Self {
             playback_sender: None,
             playback_join_handle: None,
             events_join_handle: None,
             subscribers: Arc::new(Mutex::new(HashMap::new())),
+            spectrum: SpectrumAnalyzer::spawn(config.spectrum),
+            scope,
+            entries: EntryResolver::spawn(sender.clone()),
+            library: Library::spawn(&data_dir.join("library.sqlite"), sender.clone())
+                .inspect_err(|err| eprintln!("Library is not available: {}", err)),
+            config,
         };
+        // Nothing to index without a library, which has already been logged
+        let _ = player.update_library();
 
-        let (sender, receiver) = bounded::<PlayerEvent>(0);
         player.spawn_event_thread(receiver);
-        player.spawn_playback_thread(sender);
+        player.spawn_playback_thread(
+            sender,
+            player.config.clone(),
+            cache_dir,
+            player.spectrum.tap(),
+            scope_tap,
+            player.entries.clone(),
+        );
+        // Weighted shuffling works without ratings as well
+        let _ = player.load_ratings();
 
         player
     }
 
     fn spawn_event_thread(&mut self, receiver: Receiver<PlayerEvent>) {
         let subscribers_mutex = self.subscribers.clone();
-        self.events_join_handle = Some(std::thread::spawn(move || 'receive_loop: loop {
-            let event = receiver.recv().unwrap();
-            let subscribers = subscribers_mutex.lock().unwrap();
-            for subscriber in subscribers.values() {
-                subscriber.send(event.clone()).unwrap();
-            }
-            drop(subscribers);
-
-            if let PlayerEvent::Terminated = event {
-                break 'receive_loop;
+        self.events_join_handle = Some(std::thread::spawn(move || {
+            while let Ok(event) = receiver.recv() {
+                subscribers_mutex
+                    .lock()
+                    .unwrap()
+                    .retain(|_, subscriber| subscriber.push(event.clone()));
+
+                if let PlayerEvent::Terminated = event {
+                    break;
+                }
             }
         }));
     }
 
-    fn spawn_playback_thread(&mut self, event_sender: Sender<PlayerEvent>) {
+    fn spawn_playback_thread(
+        &mut self,
+        event_sender: Sender<PlayerEvent>,
+        config: Config,
+        cache_dir: PathBuf,
+        spectrum_tap: SpectrumTap,
+        scope_tap: ScopeTap,
+        entry_resolver: EntryResolver,
+    ) {
         let (player_sender, receiver) = bounded::<PlayerCommand>(0);
-        self.playback_sender = Some(player_sender.clone());
+        let player_sender = Arc::new(player_sender);
+        let watched = Arc::new(Mutex::new(WatchedOutput::default()));
+        spawn_device_watcher(watched.clone(), Arc::downgrade(&player_sender));
+        self.playback_sender = Some(player_sender);
 
         self.playback_join_handle = Some(std::thread::spawn(move || {
-            let event_sender = event_sender.clone();
-            let audio_context = AudioContext::new(event_sender.clone());
-            let stream = AudioContext::create_cpal_stream(audio_context.clone());
-
-            // @TODO: Handle errors in playback thread without panicking
-            'receive_loop: loop {
-                match receiver.recv().unwrap() {
-                    PlayerCommand::Load(filepath) => {
-                        println!("Load filepath {}", filepath);
-                        audio_context.lock().unwrap().load(&filepath);
-
-                        event_sender.send(PlayerEvent::Playing).unwrap();
-                    }
-
-                    PlayerCommand::Play => {
-                        println!("Play");
-                        audio_context.lock().unwrap().play();
-                        event_sender.send(PlayerEvent::Playing).unwrap();
-                    }
-                    PlayerCommand::Pause => {
-                        println!("Pause");
-                        audio_context.lock().unwrap().pause();
-                        event_sender.send(PlayerEvent::Paused).unwrap();
-                    }
-                    PlayerCommand::Stop => {
-                        println!("Stop");
-                        audio_context.lock().unwrap().stop();
-                        event_sender.send(PlayerEvent::Stopped).unwrap();
-                    }
-                    PlayerCommand::Previous => {
-                        println!("Previous");
-                        audio_context.lock().unwrap().previous();
-                    }
-                    PlayerCommand::Next => {
-                        println!("Next");
-                        audio_context.lock().unwrap().next();
-                    }
-                    PlayerCommand::Terminate => {
-                        event_sender.send(PlayerEvent::Terminated).unwrap();
-                        break 'receive_loop;
-                    }
-                    PlayerCommand::Seek(position) => {
-                        println!("Seek: {}", position);
-                        audio_context.lock().unwrap().seek(position);
-                    }
+            let mut audio_context = AudioContext::new(
+                event_sender.clone(),
+                &config,
+                cache_dir,
+                spectrum_tap,
+                scope_tap,
+                entry_resolver,
+            );
+            let (error_sender, error_receiver) = unbounded::<OutputError>();
+            let mut output = Output::new(config.output.clone(), error_sender, watched);
+            output.open(&event_sender);
+
+            // A panic only restarts the playback loop. The context survives
+            // it, so the DSP chain and analyzer taps stay connected.
+            loop {
+                let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+                    playback_loop(
+                        &receiver,
+                        &error_receiver,
+                        &mut audio_context,
+                        &mut output,
+                        &event_sender,
+                    )
+                }));
+                if result.is_ok() {
+                    break;
                 }
+
+                let error = PlayerError::new(
+                    ErrorKind::Playback,
+                    "Playback thread crashed and has been restarted",
+                );
+                eprintln!("{}", error);
+                let _ = event_sender.send(error.into());
+                audio_context.recover();
+                output.open(&event_sender);
             }
         }));
     }
 
-    fn get_channel(&self) -> Sender<PlayerCommand> {
+    fn send(&self, command: PlayerCommand) -> Result<(), PlayerError> {
         let Some(ref sender) = self.playback_sender else {
-            panic!("Unable to get Player channel for sending");
+            return Err(PlayerError::unavailable());
         };
 
-        sender.clone()
+        sender.send(command).map_err(|_| PlayerError::unavailable())
     }
 
     fn terminate(&mut self) {
-        let sender = self.get_channel();
-
-        sender.send(PlayerCommand::Terminate).unwrap();
+        if let Err(err) = self.send(PlayerCommand::Terminate) {
+            eprintln!("Could not terminate playback: {}", err);
+        }
 
-        if self.playback_join_handle.is_some() {
-            let join_handle = self.playback_join_handle.take().unwrap();
-            join_handle.join().unwrap();
+        if let Some(join_handle) = self.playback_join_handle.take() {
+            let _ = join_handle.join();
         }
 
-        if self.events_join_handle.is_some() {
-            let join_handle = self.events_join_handle.take().unwrap();
-            join_handle.join().unwrap();
+        if let Some(join_handle) = self.events_join_handle.take() {
+            let _ = join_handle.join();
         }
 
         self.playback_sender = None;
     }
 
-    pub fn load(&self, filepath: &str) {
-        let sender = self.get_channel();
+    /// Load files and folders, either replacing the playlist or appending to
+    /// it
+    pub fn load(
+        &self,
+        paths: Vec<String>,
+        append: bool,
+        options: ScanOptions,
+    ) -> Result<(), PlayerError> {
+        match append {
+            true => self.send(PlayerCommand::Append(paths, options)),
+            false => self.send(PlayerCommand::Load(paths, options)),
+        }
+    }
+
+    pub fn play(&self) -> Result<(), PlayerError> {
+        self.send(PlayerCommand::Play)
+    }
+
+    pub fn pause(&self) -> Result<(), PlayerError> {
+        self.send(PlayerCommand::Pause)
+    }
+
+    pub fn stop(&self) -> Result<(), PlayerError> {
+        self.send(PlayerCommand::Stop)
+    }
+
+    pub fn previous(&self) -> Result<(), PlayerError> {
+        self.send(PlayerCommand::Previous)
+    }
+
+    pub fn next(&self) -> Result<(), PlayerError> {
+        self.send(PlayerCommand::Next)
+    }
+
+    pub fn seek(&self, position: f64) -> Result<(), PlayerError> {
+        let (reply, receiver) = bounded(1);
+        self.send(PlayerCommand::Seek(position, reply))?;
+        receiver.recv().map_err(|_| PlayerError::unavailable())?
+    }
+
+    pub fn append(&self, paths: Vec<String>, options: ScanOptions) -> Result<(), PlayerError> {
+        self.send(PlayerCommand::Append(paths, options))
+    }
+
+    pub fn insert_next(&self, paths: Vec<String>, options: ScanOptions) -> Result<(), PlayerError> {
+        self.send(PlayerCommand::InsertNext(paths, options))
+    }
+
+    pub fn remove(&self, index: usize) -> Result<(), PlayerError> {
+        self.send(PlayerCommand::Remove(index))
+    }
+
+    pub fn move_entry(&self, from: usize, to: usize) -> Result<(), PlayerError> {
+        self.send(PlayerCommand::Move { from, to })
+    }
+
+    pub fn clear(&self) -> Result<(), PlayerError> {
+        self.send(PlayerCommand::Clear)
+    }
+
+    pub fn jump(&self, index: usize) -> Result<(), PlayerError> {
+        self.send(PlayerCommand::Jump(index))
+    }
+
+    pub fn equalizer(&self) -> &EqualizerSettings {
+        &self.config.equalizer
+    }
+
+    pub fn set_equalizer_enabled(&mut self, enabled: bool) -> Result<(), PlayerError> {
+        self.config.equalizer.enabled = enabled;
+        self.apply_equalizer()
+    }
+
+    pub fn set_equalizer_band(
+        &mut self,
+        index: usize,
+        band: EqualizerBand,
+    ) -> Result<bool, PlayerError> {
+        let Some(target) = self.config.equalizer.bands.get_mut(index) else {
+            return Ok(false);
+        };
+        *target = band;
+        self.config.equalizer.preset = None;
+        self.apply_equalizer()?;
+        Ok(true)
+    }
+
+    pub fn set_equalizer_bands(&mut self, bands: Vec<EqualizerBand>) -> Result<(), PlayerError> {
+        self.config.equalizer.bands = bands;
+        self.config.equalizer.preset = None;
+        self.apply_equalizer()
+    }
+
+    pub fn apply_equalizer_preset(&mut self, name: &str) -> Result<bool, PlayerError> {
+        let Some(preset) = self.config.equalizer.find_preset(name) else {
+            return Ok(false);
+        };
+        self.config.equalizer.bands = preset.bands;
+        self.config.equalizer.preset = Some(preset.name);
+        self.apply_equalizer()?;
+        Ok(true)
+    }
+
+    /// Store the current bands as a user preset with the given name. Builtin
+    /// presets can not be overwritten.
+    pub fn save_equalizer_preset(&mut self, name: &str) -> Result<bool, PlayerError> {
+        let equalizer = &mut self.config.equalizer;
+        if equalizer.find_preset(name).is_some()
+            && !equalizer.user_presets.iter().any(|p| p.name == name)
+        {
+            return Ok(false);
+        }
+
+        equalizer.user_presets.retain(|p| p.name != name);
+        equalizer.user_presets.push(EqualizerPreset {
+            name: name.to_string(),
+            bands: equalizer.bands.clone(),
+        });
+        equalizer.preset = Some(name.to_string());
+        self.save_config()?;
+        Ok(true)
+    }
+
+    pub fn delete_equalizer_preset(&mut self, name: &str) -> Result<bool, PlayerError> {
+        let equalizer = &mut self.config.equalizer;
+        let count = equalizer.user_presets.len();
+        equalizer.user_presets.retain(|p| p.name != name);
+        if equalizer.user_presets.len() == count {
+            return Ok(false);
+        }
+
+        if equalizer.preset.as_deref() == Some(name) {
+            equalizer.preset = None;
+        }
+        self.save_config()?;
+        Ok(true)
+    }
+
+    fn apply_equalizer(&self) -> Result<(), PlayerError> {
+        self.send(PlayerCommand::ConfigureEqualizer {
+            enabled: self.config.equalizer.enabled,
+            bands: self.config.equalizer.bands.clone(),
+        })?;
+        self.save_config()
+    }
+
+    pub fn crossfeed(&self) -> &CrossfeedSettings {
+        &self.config.crossfeed
+    }
+
+    pub fn set_crossfeed_enabled(&mut self, enabled: bool) -> Result<(), PlayerError> {
+        self.config.crossfeed.enabled = enabled;
+        self.apply_crossfeed()
+    }
+
+    pub fn set_crossfeed_preset(&mut self, preset: CrossfeedPreset) -> Result<(), PlayerError> {
+        if let Some((cutoff, feed)) = preset.levels() {
+            self.config.crossfeed.cutoff = cutoff;
+            self.config.crossfeed.feed = feed;
+        }
+        self.config.crossfeed.preset = preset;
+        self.apply_crossfeed()
+    }
+
+    /// Set a custom cutoff frequency (Hz) and feed level (dB)
+    pub fn set_crossfeed_levels(&mut self, cutoff: f64, feed: f64) -> Result<(), PlayerError> {
+        self.config.crossfeed.cutoff = cutoff.clamp(crossfeed::MIN_CUTOFF, crossfeed::MAX_CUTOFF);
+        self.config.crossfeed.feed = feed.clamp(crossfeed::MIN_FEED, crossfeed::MAX_FEED);
+        self.config.crossfeed.preset = CrossfeedPreset::Custom;
+        self.apply_crossfeed()
+    }
+
+    fn apply_crossfeed(&self) -> Result<(), PlayerError> {
+        self.send(PlayerCommand::ConfigureCrossfeed(self.config.crossfeed))?;
+        self.save_config()
+    }
+
+    pub fn normalization(&self) -> &NormalizationSettings {
+        &self.config.normalization
+    }
+
+    pub fn set_normalization_mode(&mut self, mode: NormalizationMode) -> Result<(), PlayerError> {
+        self.config.normalization.mode = mode;
+        self.apply_normalization()
+    }
+
+    /// Set the target loudness in LUFS
+    pub fn set_normalization_target(&mut self, target: f64) -> Result<(), PlayerError> {
+        self.config.normalization.target = target;
+        self.apply_normalization()
+    }
+
+    pub fn set_normalization_prevent_clipping(
+        &mut self,
+        prevent_clipping: bool,
+    ) -> Result<(), PlayerError> {
+        self.config.normalization.prevent_clipping = prevent_clipping;
+        self.apply_normalization()
+    }
+
+    fn apply_normalization(&self) -> Result<(), PlayerError> {
+        self.send(PlayerCommand::ConfigureNormalization(
+            self.config.normalization,
+        ))?;
+        self.save_config()
+    }
+
+    pub fn limiter(&self) -> &LimiterSettings {
+        &self.config.limiter
+    }
+
+    pub fn set_limiter_enabled(&mut self, enabled: bool) -> Result<(), PlayerError> {
+        self.config.limiter.enabled = enabled;
+        self.apply_limiter()
+    }
+
+    /// Set the maximum output level in dBFS
+    pub fn set_limiter_ceiling(&mut self, ceiling: f64) -> Result<(), PlayerError> {
+        self.config.limiter.ceiling = ceiling.min(0.0);
+        self.apply_limiter()
+    }
+
+    fn apply_limiter(&self) -> Result<(), PlayerError> {
+        self.send(PlayerCommand::ConfigureLimiter(self.config.limiter))?;
+        self.save_config()
+    }
+
+    pub fn spectrum_settings(&self) -> &SpectrumSettings {
+        &self.config.spectrum
+    }
+
+    pub fn set_spectrum_settings(&mut self, settings: SpectrumSettings) -> Result<(), PlayerError> {
+        self.config.spectrum = self.spectrum.configure(settings);
+        self.save_config()
+    }
+
+    pub fn subscribe_to_spectrum(&self, channel: tauri::ipc::Channel<SpectrumFrame>) -> String {
+        self.spectrum.subscribe(channel)
+    }
+
+    pub fn unsubscribe_from_spectrum(&self, uuid: String) -> bool {
+        self.spectrum.unsubscribe(uuid)
+    }
+
+    pub fn scope_settings(&self) -> &ScopeSettings {
+        &self.config.scope
+    }
+
+    pub fn set_scope_settings(&mut self, settings: ScopeSettings) -> Result<(), PlayerError> {
+        self.config.scope = self.scope.configure(settings);
+        self.save_config()
+    }
+
+    pub fn subscribe_to_scope(&self, channel: tauri::ipc::Channel<ScopeFrame>) -> String {
+        self.scope.subscribe(channel)
+    }
+
+    pub fn unsubscribe_from_scope(&self, uuid: String) -> bool {
+        self.scope.unsubscribe(uuid)
+    }
+
+    pub fn preload_settings(&self) -> &PreloadSettings {
+        &self.config.preload
+    }
+
+    pub fn set_preload_memory_budget(&mut self, memory_budget: usize) -> Result<(), PlayerError> {
+        self.config.preload.memory_budget = memory_budget;
+        self.send(PlayerCommand::ConfigurePreload(self.config.preload))?;
+        self.save_config()
+    }
+
+    pub fn shuffle_mode(&self) -> ShuffleMode {
+        self.config.shuffle
+    }
+
+    pub fn set_shuffle_mode(&mut self, mode: ShuffleMode) -> Result<(), PlayerError> {
+        self.config.shuffle = mode;
+        self.send(PlayerCommand::SetShuffleMode(mode))?;
+        self.save_config()
+    }
+
+    pub fn repeat_mode(&self) -> RepeatMode {
+        self.config.repeat
+    }
+
+    pub fn set_repeat_mode(&mut self, mode: RepeatMode) -> Result<(), PlayerError> {
+        self.config.repeat = mode;
+        self.send(PlayerCommand::SetRepeatMode(mode))?;
+        self.save_config()
+    }
+
+    pub fn library_roots(&self) -> Vec<String> {
+        self.config.library.roots.clone()
+    }
+
+    /// Replace the folders of the library and index them
+    pub fn set_library_roots(&mut self, roots: Vec<String>) -> Result<(), PlayerError> {
+        self.config.library.roots = roots;
+        self.save_config()?;
+        self.update_library()
+    }
+
+    fn library(&self) -> Result<&Library, PlayerError> {
+        self.library.as_ref().map_err(PlayerError::clone)
+    }
+
+    /// Index new and changed modules of all library folders
+    pub fn update_library(&self) -> Result<(), PlayerError> {
+        self.library()?.update(self.config.library.roots.clone());
+        Ok(())
+    }
+
+    pub fn library_status(&self) -> Result<LibraryStatus, PlayerError> {
+        Ok(self.library()?.status())
+    }
+
+    pub fn query_library(&self, query: &LibraryQuery) -> Result<Vec<LibraryEntry>, PlayerError> {
+        self.library()?.query(query).map_err(|err| {
+            eprintln!("Could not query library: {}", err);
+            PlayerError::new(ErrorKind::Library, err)
+        })
+    }
+
+    pub fn ratings(&self) -> Result<HashMap<String, u8>, PlayerError> {
+        self.library()?.ratings().map_err(|err| {
+            eprintln!("Could not read ratings: {}", err);
+            PlayerError::new(ErrorKind::Library, err)
+        })
+    }
 
-        sender
-            .send(PlayerCommand::Load(String::from(filepath)))
-            .unwrap();
+    /// Rate a module from 1 to `MAX_RATING` stars, `None` removes its rating.
+    /// Weighted shuffling favours highly rated modules.
+    pub fn set_rating(&self, path: String, rating: Option<u8>) -> Result<(), PlayerError> {
+        let rating = rating.map(|rating| rating.clamp(1, MAX_RATING));
+        self.library()?.set_rating(&path, rating).map_err(|err| {
+            eprintln!("Could not rate {}: {}", path, err);
+            PlayerError::new(ErrorKind::Library, err)
+        })?;
+        self.send(PlayerCommand::SetRating(path, rating))
     }
 
-    pub fn play(&self) {
-        let sender = self.get_channel();
+    fn load_ratings(&self) -> Result<(), PlayerError> {
+        self.send(PlayerCommand::SetRatings(self.ratings()?))
+    }
+
+    /// Build the playlist from the library instead of scanning folders.
+    /// Returns the number of added modules.
+    pub fn load_from_library(
+        &self,
+        query: &LibraryQuery,
+        append: bool,
+    ) -> Result<usize, PlayerError> {
+        let files: Vec<String> = self
+            .query_library(query)?
+            .into_iter()
+            .map(|entry| entry.path)
+            .collect();
+        let count = files.len();
+        match append {
+            true => self.send(PlayerCommand::Append(files, ScanOptions::default()))?,
+            false => self.send(PlayerCommand::LoadFiles(files))?,
+        }
+        Ok(count)
+    }
 
-        sender.send(PlayerCommand::Play).unwrap();
+    pub fn output_hosts(&self) -> Vec<String> {
+        output::hosts()
     }
 
-    pub fn pause(&self) {
-        let sender = self.get_channel();
+    pub fn output_devices(&self, host: Option<&str>) -> Vec<OutputDevice> {
+        output::devices(host)
+    }
 
-        sender.send(PlayerCommand::Pause).unwrap();
+    pub fn output_settings(&self) -> &OutputSettings {
+        &self.config.output
     }
 
-    pub fn stop(&self) {
-        let sender = self.get_channel();
-        sender.send(PlayerCommand::Stop).unwrap();
+    /// Switch to another output device. `None` selects the default host or
+    /// device respectively.
+    pub fn select_output_device(
+        &mut self,
+        host: Option<String>,
+        device: Option<String>,
+    ) -> Result<(), PlayerError> {
+        self.config.output.host = host;
+        self.config.output.device = device;
+        self.apply_output()
     }
 
-    pub fn previous(&self) {
-        let sender = self.get_channel();
-        sender.send(PlayerCommand::Previous).unwrap();
+    pub fn set_pause_on_device_loss(&mut self, enabled: bool) -> Result<(), PlayerError> {
+        self.config.output.pause_on_device_loss = enabled;
+        self.apply_output()
     }
 
-    pub fn next(&self) {
-        let sender = self.get_channel();
-        sender.send(PlayerCommand::Next).unwrap();
+    fn apply_output(&self) -> Result<(), PlayerError> {
+        self.send(PlayerCommand::ConfigureOutput(self.config.output.clone()))?;
+        self.save_config()
     }
 
-    pub fn seek(&self, position: f64) {
-        let sender = self.get_channel();
-        sender.send(PlayerCommand::Seek(position)).unwrap();
+    fn save_config(&self) -> Result<(), PlayerError> {
+        self.config.save().map_err(|err| {
+            eprintln!("Could not save config: {}", err);
+            PlayerError::new(ErrorKind::Config, err)
+        })
+    }
+
+    pub fn state(&self) -> Result<PlayerState, PlayerError> {
+        let (reply, receiver) = bounded(1);
+        self.send(PlayerCommand::GetState(reply))?;
+
+        Ok(PlayerState {
+            playback: receiver.recv().map_err(|_| PlayerError::unavailable())?,
+            settings: self.config.clone(),
+        })
+    }
+
+    /// The current playlist. Entries, whose metadata hasn't been read yet, are
+    /// updated by `PlaylistEntryUpdated` events.
+    pub fn playlist(&self) -> Result<PlaylistContents, PlayerError> {
+        let (reply, receiver) = bounded(1);
+        self.send(PlayerCommand::GetPlaylist(reply))?;
+        let (files, current_index) = receiver.recv().map_err(|_| PlayerError::unavailable())?;
+
+        Ok(PlaylistContents {
+            entries: files.iter().map(|file| self.entries.get(file)).collect(),
+            current_index,
+        })
+    }
+
+    /// Replace the playlist with the entries of a playlist file. Entries,
+    /// which can't be found, are skipped and returned.
+    pub fn import_playlist(&self, filepath: &str) -> Result<PlaylistImport, PlayerError> {
+        let import = playlist_file::import(std::path::Path::new(filepath))
+            .map_err(|err| PlayerError::new(ErrorKind::Io, err).with_path(filepath))?;
+        for missing in import.missing.iter() {
+            eprintln!("Missing playlist entry {} in {}", missing, filepath);
+        }
+
+        self.send(PlayerCommand::LoadFiles(import.files.clone()))?;
+        Ok(import)
+    }
+
+    /// Take a snapshot of the current playlist to be saved with
+    /// `PlaylistExport::write`
+    pub fn export_playlist(&self) -> Result<PlaylistExport, PlayerError> {
+        let (reply, receiver) = bounded(1);
+        self.send(PlayerCommand::GetPlaylist(reply))?;
+        let (files, _) = receiver.recv().map_err(|_| PlayerError::unavailable())?;
+
+        Ok(PlaylistExport {
+            files,
+            entries: self.entries.clone(),
+        })
     }
 
     pub fn subscribe_to_events(&mut self, channel: tauri::ipc::Channel<PlayerEvent>) -> String {
         let uuid = Uuid::new_v4().to_string();
+        let subscriber = EventSubscriber::spawn(uuid.clone(), channel);
+
+        // The snapshot has to wait for the playback thread, so it is taken
+        // before locking. Otherwise dispatching events to all other
+        // subscribers would stall until the playback thread answers.
+        match self.state() {
+            Ok(state) => subscriber.push(PlayerEvent::State(Box::new(state))),
+            Err(err) => subscriber.push(PlayerEvent::from(err)),
+        };
         self.subscribers
             .lock()
             .unwrap()
-            .insert(uuid.clone(), channel);
+            .insert(uuid.clone(), subscriber);
         uuid
     }
 
@@ -555,3 +2299,285 @@ impl Player {
         self.subscribers.lock().unwrap().remove(&uuid).is_some()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODULE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../assets/hippos.mod");
+
+    fn playlist(repeat: RepeatMode) -> (Playlist, Preloader) {
+        let mut playlist = Playlist::new(ShuffleMode::Off, repeat);
+        playlist.load_files(vec![MODULE.to_string(); 3]);
+        (playlist, Preloader::spawn(PreloadSettings::default()))
+    }
+
+    #[test]
+    fn next_and_previous() {
+        let (mut playlist, mut preloader) = playlist(RepeatMode::Off);
+        assert!(playlist.current(&mut preloader).is_some());
+        assert_eq!(playlist.current_index, 0);
+
+        assert!(playlist.next(&mut preloader).is_some());
+        assert!(playlist.next(&mut preloader).is_some());
+        assert_eq!(playlist.current_index, 2);
+        assert!(playlist.next(&mut preloader).is_none());
+
+        assert!(playlist.previous(&mut preloader).is_some());
+        assert_eq!(playlist.current_index, 1);
+        assert!(playlist.previous(&mut preloader).is_some());
+        assert_eq!(playlist.current_index, 0);
+        assert!(playlist.previous(&mut preloader).is_none());
+    }
+
+    #[test]
+    fn repeat_off_ends_with_the_playlist() {
+        let (mut playlist, mut preloader) = playlist(RepeatMode::Off);
+        assert!(playlist.continues_after_end());
+        assert!(playlist.following(&mut preloader).is_some());
+        assert!(playlist.following(&mut preloader).is_some());
+        assert_eq!(playlist.current_index, 2);
+        assert!(playlist.following(&mut preloader).is_none());
+        assert!(playlist.next_or_wrap(&mut preloader).is_none());
+    }
+
+    #[test]
+    fn repeat_all_wraps_around() {
+        let (mut playlist, mut preloader) = playlist(RepeatMode::All);
+        assert!(playlist.following(&mut preloader).is_some());
+        assert!(playlist.following(&mut preloader).is_some());
+        assert_eq!(playlist.current_index, 2);
+
+        assert!(playlist.following(&mut preloader).is_some());
+        assert_eq!(playlist.current_index, 0);
+        assert!(playlist.next_or_wrap(&mut preloader).is_some());
+        assert_eq!(playlist.current_index, 1);
+    }
+
+    #[test]
+    fn repeat_one_replays_the_current_module() {
+        let (mut playlist, mut preloader) = playlist(RepeatMode::One);
+        assert!(playlist.next(&mut preloader).is_some());
+        assert_eq!(playlist.current_index, 1);
+
+        assert!(playlist.following(&mut preloader).is_some());
+        assert_eq!(playlist.current_index, 1);
+        // Skipping manually still moves on
+        assert!(playlist.next_or_wrap(&mut preloader).is_some());
+        assert_eq!(playlist.current_index, 2);
+        assert!(playlist.following(&mut preloader).is_some());
+        assert_eq!(playlist.current_index, 2);
+    }
+
+    #[test]
+    fn stop_after_current() {
+        let (mut playlist, mut preloader) = playlist(RepeatMode::StopAfterCurrent);
+        assert!(!playlist.continues_after_end());
+
+        // Manual skipping works as without repeating
+        assert!(playlist.next_or_wrap(&mut preloader).is_some());
+        assert!(playlist.next_or_wrap(&mut preloader).is_some());
+        assert_eq!(playlist.current_index, 2);
+        assert!(playlist.next_or_wrap(&mut preloader).is_none());
+        assert!(playlist.previous(&mut preloader).is_some());
+        assert_eq!(playlist.current_index, 1);
+    }
+
+    #[test]
+    fn unplayable_entries_are_skipped() {
+        let mut playlist = Playlist::new(ShuffleMode::Off, RepeatMode::All);
+        let mut preloader = Preloader::spawn(PreloadSettings::default());
+        playlist.load_files(vec![
+            String::from("/does/not/exist.mod"),
+            MODULE.to_string(),
+        ]);
+
+        assert!(playlist.current(&mut preloader).is_some());
+        assert_eq!(playlist.current_index, 1);
+        assert_eq!(playlist.errors.len(), 1);
+        // Wrapping around skips the missing entry again
+        assert!(playlist.next_or_wrap(&mut preloader).is_some());
+        assert_eq!(playlist.current_index, 1);
+    }
+
+    const RATE: i32 = 48_000;
+
+    /// Audio context with its own event channel, playing `count` modules
+    fn context(count: usize) -> (AudioContext, Receiver<PlayerEvent>, tempfile::TempDir) {
+        let cache = tempfile::tempdir().unwrap();
+        let (sender, receiver) = unbounded();
+        let config = Config::default();
+        let (_, scope_tap) = Oscilloscope::spawn(config.scope);
+        let mut context = AudioContext::new(
+            sender.clone(),
+            &config,
+            cache.path().to_path_buf(),
+            SpectrumAnalyzer::spawn(config.spectrum).tap(),
+            scope_tap,
+            EntryResolver::spawn(sender),
+        );
+        context.load_files(vec![MODULE.to_string(); count]);
+        (context, receiver, cache)
+    }
+
+    /// Transport states announced since the last call
+    fn states(events: &Receiver<PlayerEvent>) -> Vec<TransportState> {
+        events
+            .try_iter()
+            .filter_map(|event| match event {
+                PlayerEvent::StateChanged { state } => Some(state),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Seek close to the end of the current module and render blocks, until
+    /// the following one has started or playback is over. Returns the last
+    /// rendered block.
+    fn play_until_end(context: &mut AudioContext) -> Vec<f32> {
+        let index = context.playlist.current_index;
+        let duration = context.module_duration.unwrap();
+        context.seek(duration - 0.01).unwrap();
+
+        let mut data = vec![0.0; RENDER_BLOCK_FRAMES * 2];
+        while context.state == TransportState::Playing && context.playlist.current_index == index {
+            context.read(RATE, 0, &mut data);
+        }
+        data
+    }
+
+    #[test]
+    fn load_starts_playing() {
+        let (_context, events, _cache) = context(2);
+        assert_eq!(
+            states(&events),
+            [TransportState::Loading, TransportState::Playing]
+        );
+    }
+
+    #[test]
+    fn next_at_the_end_of_the_queue() {
+        let (mut context, events, _cache) = context(2);
+        context.next();
+        assert_eq!(
+            states(&events),
+            [
+                TransportState::Loading,
+                TransportState::Playing,
+                TransportState::Loading,
+                TransportState::Playing
+            ]
+        );
+
+        context.next();
+        assert_eq!(
+            states(&events),
+            [TransportState::Loading, TransportState::Ended]
+        );
+        assert!(context.module.is_none());
+    }
+
+    #[test]
+    fn remove_current_entry_while_paused() {
+        let (mut context, events, _cache) = context(3);
+        context.pause();
+        states(&events);
+
+        // The following entry is loaded, but playback stays paused
+        context.remove(0);
+        assert_eq!(
+            states(&events),
+            [
+                TransportState::Loading,
+                TransportState::Playing,
+                TransportState::Paused
+            ]
+        );
+        assert_eq!(context.playlist.files.len(), 2);
+
+        // Without a following entry the playlist has ended
+        context.jump(1);
+        context.pause();
+        states(&events);
+        context.remove(1);
+        assert_eq!(states(&events), [TransportState::Ended]);
+    }
+
+    #[test]
+    fn queue_runs_out() {
+        let (mut context, events, _cache) = context(2);
+        context.jump(1);
+        states(&events);
+
+        let data = play_until_end(&mut context);
+        assert_eq!(
+            states(&events),
+            [TransportState::Loading, TransportState::Ended]
+        );
+        // Nothing of the ended module is left in the rest of the block
+        assert_eq!(data.last(), Some(&0.0));
+    }
+
+    #[test]
+    fn module_ending_within_a_block_continues_with_the_next() {
+        let (mut context, events, _cache) = context(2);
+        states(&events);
+
+        play_until_end(&mut context);
+        assert_eq!(
+            states(&events),
+            [TransportState::Loading, TransportState::Playing]
+        );
+        assert_eq!(context.playlist.current_index, 1);
+    }
+
+    #[test]
+    fn stop_and_play() {
+        let (mut context, events, _cache) = context(2);
+        states(&events);
+
+        context.stop();
+        assert_eq!(states(&events), [TransportState::Stopped]);
+        // Stopping twice changes nothing
+        context.stop();
+        assert!(states(&events).is_empty());
+
+        context.play();
+        assert_eq!(
+            states(&events),
+            [TransportState::Loading, TransportState::Playing]
+        );
+    }
+
+    #[test]
+    fn seeking_without_module_fails() {
+        let (mut context, _events, _cache) = context(1);
+        context.stop();
+        let error = context.seek(1.0).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Playback);
+    }
+
+    #[test]
+    fn reported_position_excludes_queued_audio() {
+        let (mut context, events, _cache) = context(1);
+        context.seek(2.0).unwrap();
+        events.try_iter().for_each(drop);
+
+        let queued_frames = RATE as usize / 10;
+        let mut data = vec![0.0; RENDER_BLOCK_FRAMES * 2];
+        let reported = loop {
+            context.read(RATE, queued_frames, &mut data);
+            let update = events.try_iter().find_map(|event| match event {
+                PlayerEvent::PositionUpdated { position, .. } => Some(position),
+                _ => None,
+            });
+            if let Some(position) = update {
+                break position;
+            }
+        };
+
+        let rendered = context.module.as_ref().unwrap().get_position_seconds();
+        let latency = (queued_frames + RENDER_BLOCK_FRAMES) as f64 / RATE as f64;
+        assert!((rendered - latency - reported).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,577 @@
+use crate::entry_info::EntryInfo;
+use anyhow::{anyhow, Result};
+use quick_xml::escape::escape;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum PlaylistFormat {
+    /// M3U and M3U8, including the `#EXTINF` extension
+    M3u,
+    Pls,
+    Xspf,
+}
+
+impl PlaylistFormat {
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("m3u") | Some("m3u8") => Ok(Self::M3u),
+            Some("pls") => Ok(Self::Pls),
+            Some("xspf") => Ok(Self::Xspf),
+            _ => Err(anyhow!("Unsupported playlist format {}", path.display())),
+        }
+    }
+}
+
+/// A single entry of a playlist file
+#[derive(Clone, Default, Debug)]
+pub struct PlaylistEntry {
+    /// Location as written in the file, which may be relative or a URL
+    pub location: String,
+    pub title: Option<String>,
+    /// Duration in seconds
+    pub duration: Option<f64>,
+}
+
+impl From<EntryInfo> for PlaylistEntry {
+    fn from(info: EntryInfo) -> Self {
+        let title = match info.artist {
+            Some(artist) => format!("{} - {}", artist, info.title),
+            None => info.title,
+        };
+
+        Self {
+            location: info.path,
+            title: Some(title),
+            duration: info.duration,
+        }
+    }
+}
+
+/// Result of importing a playlist file
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaylistImport {
+    /// Absolute paths of all existing entries
+    pub files: Vec<String>,
+    /// Entries, which could not be found or are not local files
+    pub missing: Vec<String>,
+}
+
+/// Read a playlist file. Relative entries are resolved against the location
+/// of the playlist file.
+pub fn import(path: &Path) -> Result<PlaylistImport> {
+    let format = PlaylistFormat::from_path(path)?;
+    let text = decode(&std::fs::read(path)?);
+    let entries = match format {
+        PlaylistFormat::M3u => parse_m3u(&text),
+        PlaylistFormat::Pls => parse_pls(&text),
+        PlaylistFormat::Xspf => parse_xspf(&text)?,
+    };
+
+    let base = path.parent().unwrap_or(Path::new(""));
+    let mut import = PlaylistImport {
+        files: vec![],
+        missing: vec![],
+    };
+    for entry in entries {
+        let resolved = resolve(base, &entry.location, format)
+            .filter(|path| path.is_file())
+            .and_then(|path| path.to_str().map(|path| path.to_string()));
+        match resolved {
+            Some(filepath) => import.files.push(filepath),
+            None => import.missing.push(entry.location),
+        }
+    }
+
+    Ok(import)
+}
+
+/// Write the given entries, whose locations are absolute paths, as playlist
+/// file. Entries below the folder of the playlist file are stored relative to
+/// it, so the playlist can be moved together with the modules.
+pub fn export(path: &Path, entries: &[PlaylistEntry]) -> Result<()> {
+    let format = PlaylistFormat::from_path(path)?;
+    let base = path.parent().unwrap_or(Path::new(""));
+    let text = match format {
+        PlaylistFormat::M3u => write_m3u(base, entries),
+        PlaylistFormat::Pls => write_pls(base, entries),
+        PlaylistFormat::Xspf => write_xspf(base, entries),
+    };
+    std::fs::write(path, text)?;
+    Ok(())
+}
+
+/// Playlists are UTF-8 nowadays, but classic M3U files are often Latin-1
+fn decode(data: &[u8]) -> String {
+    let text = match std::str::from_utf8(data) {
+        Ok(text) => text.to_string(),
+        Err(_) => data.iter().map(|byte| *byte as char).collect(),
+    };
+    text.trim_start_matches('\u{feff}').to_string()
+}
+
+fn resolve(base: &Path, location: &str, format: PlaylistFormat) -> Option<PathBuf> {
+    if format == PlaylistFormat::Xspf || location.starts_with("file:") {
+        return match Url::parse(location) {
+            Ok(url) if url.scheme() == "file" => url.to_file_path().ok(),
+            Ok(_) => None,
+            Err(_) => Url::from_directory_path(base)
+                .ok()?
+                .join(location)
+                .ok()?
+                .to_file_path()
+                .ok(),
+        };
+    }
+
+    // Other URLs, like streams, can't be played
+    if location.contains("://") {
+        return None;
+    }
+
+    // Playlists written on Windows use backslashes
+    let location = if cfg!(windows) {
+        location.to_string()
+    } else {
+        location.replace('\\', "/")
+    };
+    Some(base.join(location))
+}
+
+fn parse_duration(value: &str) -> Option<f64> {
+    value
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|duration| *duration >= 0.0)
+}
+
+fn parse_title(value: &str) -> Option<String> {
+    Some(value.trim().to_string()).filter(|title| !title.is_empty())
+}
+
+fn parse_m3u(text: &str) -> Vec<PlaylistEntry> {
+    let mut entries = vec![];
+    let mut info: Option<PlaylistEntry> = None;
+    for line in text.lines().map(str::trim) {
+        if let Some(extinf) = line.strip_prefix("#EXTINF:") {
+            // The duration may be followed by attributes, the title follows
+            // after the first comma.
+            let (head, title) = extinf.split_once(',').unwrap_or((extinf, ""));
+            info = Some(PlaylistEntry {
+                location: String::new(),
+                title: parse_title(title),
+                duration: head.split_whitespace().next().and_then(parse_duration),
+            });
+        } else if !line.is_empty() && !line.starts_with('#') {
+            let mut entry = info.take().unwrap_or_default();
+            entry.location = line.to_string();
+            entries.push(entry);
+        }
+    }
+
+    entries
+}
+
+fn parse_pls(text: &str) -> Vec<PlaylistEntry> {
+    let mut entries: BTreeMap<usize, PlaylistEntry> = BTreeMap::new();
+    for line in text.lines().map(str::trim) {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_lowercase();
+        let field = key.trim_end_matches(|c: char| c.is_ascii_digit());
+        let Ok(index) = key[field.len()..].parse::<usize>() else {
+            continue;
+        };
+
+        let entry = entries.entry(index).or_default();
+        match field {
+            "file" => entry.location = value.trim().to_string(),
+            "title" => entry.title = parse_title(value),
+            "length" => entry.duration = parse_duration(value),
+            _ => {}
+        }
+    }
+
+    entries
+        .into_values()
+        .filter(|entry| !entry.location.is_empty())
+        .collect()
+}
+
+fn parse_xspf(text: &str) -> Result<Vec<PlaylistEntry>> {
+    let mut reader = Reader::from_str(text);
+    reader.config_mut().trim_text(true);
+
+    let mut entries = vec![];
+    let mut track: Option<PlaylistEntry> = None;
+    let mut element: Option<Vec<u8>> = None;
+    loop {
+        match reader.read_event()? {
+            Event::Start(start) => {
+                let name = start.local_name().as_ref().to_vec();
+                if name == b"track" {
+                    track = Some(PlaylistEntry::default());
+                } else {
+                    element = Some(name);
+                }
+            }
+            Event::Text(text) => {
+                let (Some(track), Some(element)) = (track.as_mut(), element.as_deref()) else {
+                    continue;
+                };
+                let value = text.unescape()?;
+                match element {
+                    // A track may list alternative locations, the first one
+                    // is used
+                    b"location" if track.location.is_empty() => {
+                        track.location = value.trim().to_string()
+                    }
+                    b"title" => track.title = parse_title(&value),
+                    // XSPF durations are in milliseconds
+                    b"duration" => track.duration = parse_duration(&value).map(|ms| ms / 1000.0),
+                    _ => {}
+                }
+            }
+            Event::End(end) => {
+                if end.local_name().as_ref() == b"track" {
+                    if let Some(track) = track.take().filter(|t| !t.location.is_empty()) {
+                        entries.push(track);
+                    }
+                }
+                element = None;
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Location of an entry relative to the playlist folder, if it is inside of
+/// it
+fn relative_location(base: &Path, location: &str) -> String {
+    match Path::new(location).strip_prefix(base) {
+        Ok(relative) => relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"),
+        Err(_) => location.to_string(),
+    }
+}
+
+fn fallback_title(entry: &PlaylistEntry) -> String {
+    entry.title.clone().unwrap_or_else(|| {
+        Path::new(&entry.location)
+            .file_stem()
+            .map_or(entry.location.clone(), |stem| {
+                stem.to_string_lossy().to_string()
+            })
+    })
+}
+
+fn write_m3u(base: &Path, entries: &[PlaylistEntry]) -> String {
+    let mut text = String::from("#EXTM3U\n");
+    for entry in entries {
+        let duration = entry
+            .duration
+            .map_or(-1, |duration| duration.round() as i64);
+        let _ = writeln!(text, "#EXTINF:{},{}", duration, fallback_title(entry));
+        let _ = writeln!(text, "{}", relative_location(base, &entry.location));
+    }
+    text
+}
+
+fn write_pls(base: &Path, entries: &[PlaylistEntry]) -> String {
+    let mut text = String::from("[playlist]\n");
+    for (index, entry) in entries.iter().enumerate() {
+        let number = index + 1;
+        let duration = entry
+            .duration
+            .map_or(-1, |duration| duration.round() as i64);
+        let _ = writeln!(
+            text,
+            "File{}={}",
+            number,
+            relative_location(base, &entry.location)
+        );
+        let _ = writeln!(text, "Title{}={}", number, fallback_title(entry));
+        let _ = writeln!(text, "Length{}={}", number, duration);
+    }
+    let _ = writeln!(text, "NumberOfEntries={}", entries.len());
+    let _ = writeln!(text, "Version=2");
+    text
+}
+
+fn write_xspf(base: &Path, entries: &[PlaylistEntry]) -> String {
+    let base = Url::from_directory_path(base).ok();
+    let mut text = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n  <trackList>\n",
+    );
+    for entry in entries {
+        let Ok(url) = Url::from_file_path(&entry.location) else {
+            continue;
+        };
+        let location = base
+            .as_ref()
+            .and_then(|base| base.make_relative(&url))
+            .filter(|relative| !relative.starts_with("../"))
+            .unwrap_or(url.to_string());
+
+        text.push_str("    <track>\n");
+        let _ = writeln!(text, "      <location>{}</location>", escape(&location));
+        if let Some(ref title) = entry.title {
+            let _ = writeln!(text, "      <title>{}</title>", escape(title));
+        }
+        if let Some(duration) = entry.duration {
+            let _ = writeln!(
+                text,
+                "      <duration>{}</duration>",
+                (duration * 1000.0).round() as u64
+            );
+        }
+        text.push_str("    </track>\n");
+    }
+    text.push_str("  </trackList>\n</playlist>\n");
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(location: &str, title: Option<&str>, duration: Option<f64>) -> PlaylistEntry {
+        PlaylistEntry {
+            location: location.to_string(),
+            title: title.map(str::to_string),
+            duration,
+        }
+    }
+
+    fn summary(entries: &[PlaylistEntry]) -> Vec<(&str, Option<&str>, Option<f64>)> {
+        entries
+            .iter()
+            .map(|entry| {
+                (
+                    entry.location.as_str(),
+                    entry.title.as_deref(),
+                    entry.duration,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_m3u() {
+        let text = "#EXTM3U\n\
+                    #EXTINF:123,Purple Motion - Satellite One\n\
+                    satellite.s3m\n\
+                    \n\
+                    # A comment\n\
+                    #EXTINF:-1 tvg-id=\"x\",  Spaced Title  \n\
+                    sub\\2nd_reality.s3m\n\
+                    /absolute/path.xm\n\
+                    #EXTINF:abc,\n\
+                    http://example.com/stream\n";
+
+        assert_eq!(
+            summary(&parse_m3u(text)),
+            [
+                (
+                    "satellite.s3m",
+                    Some("Purple Motion - Satellite One"),
+                    Some(123.0)
+                ),
+                ("sub\\2nd_reality.s3m", Some("Spaced Title"), None),
+                ("/absolute/path.xm", None, None),
+                ("http://example.com/stream", None, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_pls() {
+        let text = "[playlist]\n\
+                    File2=b.mod\n\
+                    Title2=Second\n\
+                    File1=a.mod\n\
+                    Length1=61\n\
+                    title1=First\n\
+                    Length2=-1\n\
+                    Title3=Entry without file\n\
+                    NumberOfEntries=3\n\
+                    Version=2\n";
+
+        assert_eq!(
+            summary(&parse_pls(text)),
+            [
+                ("a.mod", Some("First"), Some(61.0)),
+                ("b.mod", Some("Second"), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_xspf() {
+        let text = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <playlist version="1" xmlns="http://xspf.org/ns/0/">
+              <title>Not a track</title>
+              <trackList>
+                <track>
+                  <location>file:///music/a%20b.mod</location>
+                  <location>file:///music/alternative.mod</location>
+                  <title>Rock &amp; Roll</title>
+                  <duration>90500</duration>
+                </track>
+                <track>
+                  <title>Without location</title>
+                </track>
+                <track>
+                  <location>relative/c.xm</location>
+                </track>
+              </trackList>
+            </playlist>"#;
+
+        assert_eq!(
+            summary(&parse_xspf(text).unwrap()),
+            [
+                ("file:///music/a%20b.mod", Some("Rock & Roll"), Some(90.5)),
+                ("relative/c.xm", None, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn decodes_latin1_and_strips_bom() {
+        assert_eq!(decode(b"caf\xe9.mod"), "café.mod");
+        assert_eq!(decode("\u{feff}#EXTM3U".as_bytes()), "#EXTM3U");
+    }
+
+    #[test]
+    fn resolves_locations() {
+        let base = Path::new("/music/lists");
+        let m3u = |location| resolve(base, location, PlaylistFormat::M3u);
+
+        assert_eq!(m3u("a.mod"), Some(PathBuf::from("/music/lists/a.mod")));
+        assert_eq!(m3u("/other/b.mod"), Some(PathBuf::from("/other/b.mod")));
+        assert_eq!(
+            m3u("file:///other/c%20d.mod"),
+            Some(PathBuf::from("/other/c d.mod"))
+        );
+        assert_eq!(m3u("http://example.com/stream"), None);
+        assert_eq!(
+            resolve(base, "../mods/e.mod", PlaylistFormat::Xspf),
+            Some(PathBuf::from("/music/mods/e.mod"))
+        );
+        if !cfg!(windows) {
+            assert_eq!(
+                m3u("sub\\f.mod"),
+                Some(PathBuf::from("/music/lists/sub/f.mod"))
+            );
+        }
+    }
+
+    #[test]
+    fn import_reports_missing_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        std::fs::write(dir.path().join("a.mod"), b"").unwrap();
+        std::fs::write(dir.path().join("sub").join("b.mod"), b"").unwrap();
+
+        let playlist = dir.path().join("list.m3u");
+        std::fs::write(
+            &playlist,
+            "#EXTM3U\na.mod\nsub/b.mod\nmissing.mod\nhttp://example.com/stream\nsub\n",
+        )
+        .unwrap();
+
+        let import = import(&playlist).unwrap();
+        let path = |relative: &str| dir.path().join(relative).to_str().unwrap().to_string();
+        assert_eq!(import.files, [path("a.mod"), path("sub/b.mod")]);
+        assert_eq!(
+            import.missing,
+            ["missing.mod", "http://example.com/stream", "sub"]
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let modules = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("sub dir")).unwrap();
+
+        let locations = [
+            dir.path().join("a.mod"),
+            dir.path().join("sub dir").join("b & c.xm"),
+            modules.path().join("outside.it"),
+        ];
+        for location in locations.iter() {
+            std::fs::write(location, b"").unwrap();
+        }
+        let locations: Vec<&str> = locations
+            .iter()
+            .map(|location| location.to_str().unwrap())
+            .collect();
+        let entries = [
+            entry(locations[0], Some("First"), Some(61.0)),
+            entry(locations[1], None, None),
+            entry(locations[2], Some("Third"), Some(3.5)),
+        ];
+
+        for name in ["list.m3u", "list.m3u8", "list.pls", "list.xspf"] {
+            let path = dir.path().join(name);
+            export(&path, &entries).unwrap();
+
+            let text = std::fs::read_to_string(&path).unwrap();
+            assert!(!text.contains(dir.path().to_str().unwrap()), "{}", name);
+
+            let import = import(&path).unwrap();
+            assert_eq!(import.files, locations, "{}", name);
+            assert!(import.missing.is_empty(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn written_entries_keep_titles_and_durations() {
+        let base = Path::new("/music");
+        let entries = [
+            entry("/music/a.mod", Some("First"), Some(61.0)),
+            entry("/music/sub/b.mod", None, None),
+        ];
+
+        assert_eq!(
+            summary(&parse_m3u(&write_m3u(base, &entries))),
+            [
+                ("a.mod", Some("First"), Some(61.0)),
+                ("sub/b.mod", Some("b"), None),
+            ]
+        );
+        assert_eq!(
+            summary(&parse_pls(&write_pls(base, &entries))),
+            [
+                ("a.mod", Some("First"), Some(61.0)),
+                ("sub/b.mod", Some("b"), None),
+            ]
+        );
+        assert_eq!(
+            summary(&parse_xspf(&write_xspf(base, &entries)).unwrap()),
+            [
+                ("a.mod", Some("First"), Some(61.0)),
+                ("sub/b.mod", None, None),
+            ]
+        );
+    }
+}
@@ -0,0 +1,150 @@
+use crate::openmpt;
+use crossbeam::channel::{unbounded, Receiver, Sender};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Eq, PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PreloadSettings {
+    /// Maximum size of preloaded module files in MiB. 0 disables preloading.
+    pub memory_budget: usize,
+}
+
+impl Default for PreloadSettings {
+    fn default() -> Self {
+        Self { memory_budget: 64 }
+    }
+}
+
+impl PreloadSettings {
+    fn budget_bytes(&self) -> usize {
+        self.memory_budget * 1024 * 1024
+    }
+}
+
+struct Preloaded {
+    filepath: String,
+    /// `None` if the file is not a module, so it can be skipped right away
+    module: Option<openmpt::module::Module>,
+    size: usize,
+}
+
+/// Result of a preload request. Files, which could not be read, are reported
+/// by path, so they can be requested again later.
+type PreloadResult = Result<Preloaded, String>;
+
+fn preload_worker(receiver: Receiver<String>, sender: Sender<PreloadResult>) {
+    while let Ok(filepath) = receiver.recv() {
+        let result = match std::fs::read(&filepath) {
+            Ok(data) => Ok(Preloaded {
+                module: openmpt::module::Module::try_from_memory(&data).ok(),
+                size: data.len(),
+                filepath,
+            }),
+            Err(err) => {
+                eprintln!("Could not preload {}: {}", filepath, err);
+                Err(filepath)
+            }
+        };
+        if sender.send(result).is_err() {
+            return;
+        }
+    }
+}
+
+/// Opens upcoming modules on a background thread, so switching tracks never
+/// has to wait for file IO or module parsing.
+///
+/// The memory use of a module is estimated by its file size.
+pub struct Preloader {
+    settings: PreloadSettings,
+    request_sender: Sender<String>,
+    result_receiver: Receiver<PreloadResult>,
+    /// Files, which should be kept preloaded
+    wanted: Vec<String>,
+    requested: HashSet<String>,
+    modules: HashMap<String, Preloaded>,
+}
+
+impl Preloader {
+    pub fn spawn(settings: PreloadSettings) -> Self {
+        let (request_sender, request_receiver) = unbounded();
+        let (result_sender, result_receiver) = unbounded();
+        std::thread::spawn(move || preload_worker(request_receiver, result_sender));
+
+        Self {
+            settings,
+            request_sender,
+            result_receiver,
+            wanted: vec![],
+            requested: HashSet::new(),
+            modules: HashMap::new(),
+        }
+    }
+
+    pub fn configure(&mut self, settings: PreloadSettings) {
+        self.settings = settings;
+        let wanted = std::mem::take(&mut self.wanted);
+        self.modules.clear();
+        self.requested.clear();
+        self.preload(wanted);
+    }
+
+    fn used_bytes(&self) -> usize {
+        self.modules.values().map(|preloaded| preloaded.size).sum()
+    }
+
+    /// Keep the given files preloaded, ordered by priority. All other
+    /// preloaded modules are released.
+    pub fn preload(&mut self, wanted: Vec<String>) {
+        self.collect();
+        self.modules.retain(|filepath, _| wanted.contains(filepath));
+        self.requested.retain(|filepath| wanted.contains(filepath));
+
+        if self.settings.memory_budget > 0 {
+            for filepath in wanted.iter() {
+                if !self.modules.contains_key(filepath) && self.requested.insert(filepath.clone()) {
+                    let _ = self.request_sender.send(filepath.clone());
+                }
+            }
+        }
+
+        self.wanted = wanted;
+    }
+
+    /// Move finished modules out of the worker channel
+    fn collect(&mut self) {
+        while let Ok(result) = self.result_receiver.try_recv() {
+            let preloaded = match result {
+                Ok(preloaded) => preloaded,
+                Err(filepath) => {
+                    // Allow the next preload call to retry the file
+                    self.requested.remove(&filepath);
+                    continue;
+                }
+            };
+            if !self.requested.remove(&preloaded.filepath) {
+                continue;
+            }
+
+            if self.used_bytes() + preloaded.size > self.settings.budget_bytes() {
+                eprintln!(
+                    "Not preloading {}, memory budget exceeded",
+                    preloaded.filepath
+                );
+                continue;
+            }
+
+            self.modules.insert(preloaded.filepath.clone(), preloaded);
+        }
+    }
+
+    /// Take a preloaded module. The outer `None` means the file has not been
+    /// preloaded, the inner one that it isn't a module.
+    pub fn take(&mut self, filepath: &str) -> Option<Option<openmpt::module::Module>> {
+        self.collect();
+        self.modules
+            .remove(filepath)
+            .map(|preloaded| preloaded.module)
+    }
+}
@@ -0,0 +1,697 @@
+use crate::error::{ErrorKind, PlayerError};
+use crate::openmpt;
+use crossbeam::channel::{unbounded, Receiver, Sender};
+use glob::{MatchOptions, Pattern};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How files and folders of the same directory are ordered
+#[derive(Eq, PartialEq, Copy, Clone, Default, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ScanOrder {
+    /// Sort files and folders together by name
+    #[default]
+    Interleaved,
+    FoldersFirst,
+    FilesFirst,
+}
+
+/// Options for turning files and folders into a playlist
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ScanOptions {
+    pub recursive: bool,
+    pub order: ScanOrder,
+    /// File extensions to include, without the dot. Defaults to the formats
+    /// libopenmpt supports, empty includes all files.
+    pub extensions: Vec<String>,
+    /// Glob patterns of files and folders to skip, matched against the name
+    /// and the full path
+    pub exclude: Vec<String>,
+    /// Include files and folders starting with a dot
+    pub include_hidden: bool,
+    /// Descend into symlinked folders. Loops are detected either way.
+    pub follow_symlinks: bool,
+    /// Maximum number of folder levels below the given folders. `None` scans
+    /// all levels.
+    pub max_depth: Option<usize>,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            recursive: true,
+            order: ScanOrder::default(),
+            extensions: openmpt::supported_extensions(),
+            exclude: vec![],
+            include_hidden: false,
+            follow_symlinks: true,
+            max_depth: None,
+        }
+    }
+}
+
+/// Compare names the way humans do, so "track 2" comes before "track 10"
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+    // Case and leading zeros only decide between otherwise equal names
+    let mut tie = Ordering::Equal;
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return tie,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let x = take_number(&mut a);
+                let y = take_number(&mut b);
+                let (x_value, y_value) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                let ordering = x_value
+                    .len()
+                    .cmp(&y_value.len())
+                    .then_with(|| x_value.cmp(y_value));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                tie = tie.then_with(|| x.len().cmp(&y.len()));
+            }
+            (Some(x), Some(y)) => {
+                let ordering = x.to_lowercase().cmp(y.to_lowercase());
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                tie = tie.then_with(|| x.cmp(&y));
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+fn take_number(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut number = String::new();
+    while let Some(digit) = chars.next_if(|c| c.is_ascii_digit()) {
+        number.push(digit);
+    }
+    number
+}
+
+struct Filter {
+    extensions: Vec<String>,
+    exclude: Vec<Pattern>,
+    include_hidden: bool,
+}
+
+impl Filter {
+    fn new(options: &ScanOptions, errors: &mut Vec<PlayerError>) -> Self {
+        let exclude = options
+            .exclude
+            .iter()
+            .filter_map(|pattern| match Pattern::new(pattern) {
+                Ok(pattern) => Some(pattern),
+                Err(err) => {
+                    errors.push(PlayerError::new(
+                        ErrorKind::Io,
+                        format!("Invalid exclude pattern {}: {}", pattern, err),
+                    ));
+                    None
+                }
+            })
+            .collect();
+
+        Self {
+            extensions: options
+                .extensions
+                .iter()
+                .map(|extension| extension.trim_start_matches('.').to_lowercase())
+                .collect(),
+            exclude,
+            include_hidden: options.include_hidden,
+        }
+    }
+
+    fn is_excluded(&self, path: &Path, name: &str) -> bool {
+        let options = MatchOptions {
+            case_sensitive: false,
+            ..MatchOptions::new()
+        };
+        (!self.include_hidden && name.starts_with('.'))
+            || self.exclude.iter().any(|pattern| {
+                pattern.matches_with(name, options) || pattern.matches_path_with(path, options)
+            })
+    }
+
+    fn is_included_file(&self, path: &Path) -> bool {
+        if self.extensions.is_empty() {
+            return true;
+        }
+
+        path.extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase())
+            .is_some_and(|extension| self.extensions.contains(&extension))
+    }
+}
+
+/// Summary of a scan, reported to the frontend
+#[derive(Serialize, Clone, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanReport {
+    pub files: usize,
+    pub directories: usize,
+    /// Symlinked folders, which have been skipped, because following them is
+    /// disabled
+    pub skipped_symlinks: usize,
+    /// Folders, which have been reached before, e.g. through a symlink loop
+    pub skipped_cycles: usize,
+    /// Folders below the maximum depth
+    pub skipped_depth: usize,
+    pub errors: Vec<PlayerError>,
+}
+
+#[derive(Serialize, Clone, Copy, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanProgress {
+    /// Files and folders, which have been looked at
+    pub files_seen: usize,
+    /// Files, which have been added to the playlist
+    pub modules_found: usize,
+}
+
+pub enum ScanMessage {
+    /// Next files in playlist order
+    Files(Vec<String>),
+    Progress(ScanProgress),
+    /// Always the last message, unless the scan has been cancelled
+    Finished(ScanReport),
+}
+
+/// Identity of a directory, independent of the path it has been reached by
+#[cfg(unix)]
+type DirectoryKey = (u64, u64);
+#[cfg(not(unix))]
+type DirectoryKey = PathBuf;
+
+#[cfg(unix)]
+fn directory_key(path: &Path) -> std::io::Result<DirectoryKey> {
+    use std::os::unix::fs::MetadataExt;
+    let metadata = std::fs::metadata(path)?;
+    Ok((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn directory_key(path: &Path) -> std::io::Result<DirectoryKey> {
+    std::fs::canonicalize(path)
+}
+
+struct Scanner {
+    options: ScanOptions,
+    filter: Filter,
+    visited: HashSet<DirectoryKey>,
+    /// Files, which haven't been sent yet
+    batch: Vec<String>,
+    progress: ScanProgress,
+    last_progress: Instant,
+    report: ScanReport,
+    sender: Sender<ScanMessage>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Scanner {
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(AtomicOrdering::Relaxed)
+    }
+
+    /// Send the files found so far, and the progress if it is due
+    fn flush(&mut self) {
+        if !self.batch.is_empty() {
+            let batch = std::mem::take(&mut self.batch);
+            let _ = self.sender.send(ScanMessage::Files(batch));
+        }
+
+        if self.last_progress.elapsed() >= PROGRESS_INTERVAL {
+            let _ = self.sender.send(ScanMessage::Progress(self.progress));
+            self.last_progress = Instant::now();
+        }
+    }
+
+    fn error(&mut self, path: &Path, err: impl std::fmt::Display) {
+        self.report
+            .errors
+            .push(PlayerError::new(ErrorKind::Io, err).with_path(path.to_string_lossy()));
+    }
+
+    fn add_file(&mut self, path: &Path) {
+        match path.to_str() {
+            Some(path) => {
+                self.batch.push(path.to_string());
+                self.report.files += 1;
+                self.progress.modules_found += 1;
+            }
+            None => eprintln!("Skipping non UTF-8 path {}", path.display()),
+        }
+    }
+
+    fn read_directory(&mut self, directory: &Path, depth: usize) {
+        if self.is_cancelled() {
+            return;
+        }
+
+        match directory_key(directory) {
+            Ok(key) => {
+                if !self.visited.insert(key) {
+                    eprintln!("Skipping already scanned folder {}", directory.display());
+                    self.report.skipped_cycles += 1;
+                    return;
+                }
+            }
+            Err(err) => return self.error(directory, err),
+        }
+
+        let entries = match std::fs::read_dir(directory) {
+            Ok(entries) => entries,
+            Err(err) => return self.error(directory, err),
+        };
+        self.report.directories += 1;
+
+        let mut children: Vec<(String, PathBuf, bool)> = vec![];
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    self.error(directory, err);
+                    continue;
+                }
+            };
+            self.progress.files_seen += 1;
+            let path = entry.path();
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                eprintln!("Skipping non UTF-8 path {}", path.display());
+                continue;
+            };
+            if self.filter.is_excluded(&path, name) {
+                continue;
+            }
+
+            let is_symlink = entry
+                .file_type()
+                .map(|file_type| file_type.is_symlink())
+                .unwrap_or(false);
+            // Follows symlinks, so broken ones are reported here
+            let metadata = match std::fs::metadata(&path) {
+                Ok(metadata) => metadata,
+                Err(err) => {
+                    self.error(&path, err);
+                    continue;
+                }
+            };
+
+            if metadata.is_dir() {
+                if !self.options.recursive {
+                    continue;
+                }
+                if is_symlink && !self.options.follow_symlinks {
+                    self.report.skipped_symlinks += 1;
+                    continue;
+                }
+                if self.options.max_depth.is_some_and(|max| depth >= max) {
+                    self.report.skipped_depth += 1;
+                    continue;
+                }
+                children.push((name.to_string(), path, true));
+            } else if metadata.is_file() && self.filter.is_included_file(&path) {
+                children.push((name.to_string(), path, false));
+            }
+        }
+
+        let order = self.options.order;
+        children.sort_by(|(a, _, a_is_dir), (b, _, b_is_dir)| {
+            let group = match order {
+                ScanOrder::Interleaved => Ordering::Equal,
+                ScanOrder::FoldersFirst => b_is_dir.cmp(a_is_dir),
+                ScanOrder::FilesFirst => a_is_dir.cmp(b_is_dir),
+            };
+            group.then_with(|| natural_cmp(a, b))
+        });
+
+        for (_, path, is_dir) in children {
+            if is_dir {
+                // Everything found so far comes first in the playlist
+                self.flush();
+                self.read_directory(&path, depth + 1);
+            } else {
+                self.add_file(&path);
+            }
+        }
+        self.flush();
+    }
+}
+
+/// Expand the given files and folders into a list of files on a background
+/// thread. Explicitly given files are always included, the contents of
+/// folders are filtered and sorted according to the options.
+///
+/// Files are sent in playlist order as soon as they have been found.
+/// Unreadable entries are skipped and collected in the report, so a single
+/// broken directory doesn't prevent playing the rest.
+pub struct ScanJob {
+    receiver: Receiver<ScanMessage>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl ScanJob {
+    pub fn spawn(paths: Vec<String>, options: ScanOptions) -> Self {
+        let (sender, receiver) = unbounded();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let mut report = ScanReport::default();
+        let filter = Filter::new(&options, &mut report.errors);
+        let mut scanner = Scanner {
+            options,
+            filter,
+            visited: HashSet::new(),
+            batch: vec![],
+            progress: ScanProgress::default(),
+            last_progress: Instant::now(),
+            report,
+            sender,
+            cancelled: cancelled.clone(),
+        };
+
+        std::thread::spawn(move || {
+            for path in paths {
+                let path = Path::new(&path);
+                match std::fs::metadata(path) {
+                    Ok(metadata) if metadata.is_dir() => scanner.read_directory(path, 0),
+                    Ok(_) => scanner.add_file(path),
+                    Err(err) => scanner.error(path, err),
+                }
+            }
+            scanner.flush();
+
+            if !scanner.is_cancelled() {
+                let _ = scanner.sender.send(ScanMessage::Progress(scanner.progress));
+                let _ = scanner.sender.send(ScanMessage::Finished(scanner.report));
+            }
+        });
+
+        Self {
+            receiver,
+            cancelled,
+        }
+    }
+
+    /// Wait for the next message. Returns `None` once the scan is over.
+    pub fn recv(&self) -> Option<ScanMessage> {
+        self.receiver.recv().ok()
+    }
+
+    /// Messages, which have arrived since the last call
+    pub fn messages(&self) -> Vec<ScanMessage> {
+        self.receiver.try_iter().collect()
+    }
+}
+
+impl Drop for ScanJob {
+    fn drop(&mut self) {
+        self.cancelled.store(true, AtomicOrdering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn scan(root: &Path, options: ScanOptions) -> (Vec<String>, ScanReport) {
+        let job = ScanJob::spawn(vec![root.to_string_lossy().to_string()], options);
+        let mut files = vec![];
+        let mut report = None;
+        while let Some(message) = job.recv() {
+            match message {
+                ScanMessage::Files(batch) => files.extend(batch),
+                ScanMessage::Progress(_) => {}
+                ScanMessage::Finished(finished) => report = Some(finished),
+            }
+        }
+        (files, report.expect("scan has not finished"))
+    }
+
+    fn names(root: &Path, files: &[String]) -> Vec<String> {
+        files
+            .iter()
+            .map(|file| {
+                Path::new(file)
+                    .strip_prefix(root)
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect()
+    }
+
+    /// root/a.mod, root/sub/b.mod, root/sub/deeper/c.mod
+    fn tree() -> TempDir {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir_all(root.path().join("sub/deeper")).unwrap();
+        fs::write(root.path().join("a.mod"), b"").unwrap();
+        fs::write(root.path().join("sub/b.mod"), b"").unwrap();
+        fs::write(root.path().join("sub/deeper/c.mod"), b"").unwrap();
+        root
+    }
+
+    #[test]
+    fn scans_recursively() {
+        let root = tree();
+        let (files, report) = scan(root.path(), ScanOptions::default());
+        assert_eq!(
+            names(root.path(), &files),
+            ["a.mod", "sub/b.mod", "sub/deeper/c.mod"]
+        );
+        assert_eq!(report.files, 3);
+        assert_eq!(report.directories, 3);
+        assert!(report.errors.is_empty());
+    }
+
+    #[test]
+    fn limits_depth() {
+        let root = tree();
+        let options = ScanOptions {
+            max_depth: Some(1),
+            ..ScanOptions::default()
+        };
+        let (files, report) = scan(root.path(), options);
+        assert_eq!(names(root.path(), &files), ["a.mod", "sub/b.mod"]);
+        assert_eq!(report.skipped_depth, 1);
+
+        let options = ScanOptions {
+            max_depth: Some(0),
+            ..ScanOptions::default()
+        };
+        let (files, report) = scan(root.path(), options);
+        assert_eq!(names(root.path(), &files), ["a.mod"]);
+        assert_eq!(report.skipped_depth, 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn terminates_on_symlink_loops() {
+        let root = tree();
+        std::os::unix::fs::symlink(root.path(), root.path().join("sub/loop")).unwrap();
+
+        let (files, report) = scan(root.path(), ScanOptions::default());
+        assert_eq!(
+            names(root.path(), &files),
+            ["a.mod", "sub/b.mod", "sub/deeper/c.mod"]
+        );
+        assert_eq!(report.skipped_cycles, 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn skips_symlinked_folders_if_disabled() {
+        let root = tree();
+        let outside = tempfile::tempdir().unwrap();
+        fs::write(outside.path().join("linked.mod"), b"").unwrap();
+        std::os::unix::fs::symlink(outside.path(), root.path().join("link")).unwrap();
+
+        let (files, _) = scan(root.path(), ScanOptions::default());
+        assert!(names(root.path(), &files).contains(&String::from("link/linked.mod")));
+
+        let options = ScanOptions {
+            follow_symlinks: false,
+            ..ScanOptions::default()
+        };
+        let (files, report) = scan(root.path(), options);
+        assert_eq!(
+            names(root.path(), &files),
+            ["a.mod", "sub/b.mod", "sub/deeper/c.mod"]
+        );
+        assert_eq!(report.skipped_symlinks, 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[ignore = "needs to run as regular user, permissions don't apply to root"]
+    fn skips_unreadable_folders() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = tree();
+        let locked = root.path().join("sub/deeper");
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+        let readable = fs::read_dir(&locked).is_ok();
+
+        let (files, report) = scan(root.path(), ScanOptions::default());
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(!readable, "folder is readable despite its permissions");
+
+        assert_eq!(names(root.path(), &files), ["a.mod", "sub/b.mod"]);
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].kind, ErrorKind::Io);
+        assert_eq!(
+            report.errors[0].path.as_deref(),
+            Some(locked.to_string_lossy().as_ref())
+        );
+    }
+
+    #[test]
+    fn natural_order() {
+        let cases = [
+            ("track2", "track10", Ordering::Less),
+            ("track10", "track2", Ordering::Greater),
+            ("track 9.mod", "track 10.mod", Ordering::Less),
+            ("Track3", "track2", Ordering::Greater),
+            ("track2", "Track3", Ordering::Less),
+            ("Track2", "track2", Ordering::Less),
+            ("track2", "track2", Ordering::Equal),
+            ("track02", "track2", Ordering::Greater),
+            ("track02", "track3", Ordering::Less),
+            ("a1b2", "a1b10", Ordering::Less),
+            ("track", "track1", Ordering::Less),
+            ("ä", "Ä", Ordering::Greater),
+            ("", "a", Ordering::Less),
+        ];
+        for (a, b, expected) in cases {
+            assert_eq!(natural_cmp(a, b), expected, "{} <=> {}", a, b);
+        }
+    }
+
+    #[test]
+    fn filters_files() {
+        let options = ScanOptions {
+            extensions: vec![String::from("mod"), String::from(".XM")],
+            exclude: vec![String::from("*.bak.*"), String::from("/music/old/*")],
+            ..ScanOptions::default()
+        };
+        let mut errors = vec![];
+        let filter = Filter::new(&options, &mut errors);
+        assert!(errors.is_empty());
+
+        let cases = [
+            ("/music/a.mod", true),
+            ("/music/b.MOD", true),
+            ("/music/c.xm", true),
+            ("/music/d.it", false),
+            ("/music/e", false),
+            ("/music/.hidden.mod", false),
+            ("/music/f.BAK.mod", false),
+            ("/music/old/g.mod", false),
+            ("/music/older/h.mod", true),
+        ];
+        for (path, expected) in cases {
+            let path = Path::new(path);
+            let name = path.file_name().unwrap().to_str().unwrap();
+            let included = !filter.is_excluded(path, name) && filter.is_included_file(path);
+            assert_eq!(included, expected, "{}", path.display());
+        }
+    }
+
+    #[test]
+    fn filters_with_options() {
+        let mut errors = vec![];
+        let options = ScanOptions {
+            extensions: vec![],
+            include_hidden: true,
+            exclude: vec![String::from("[")],
+            ..ScanOptions::default()
+        };
+        let filter = Filter::new(&options, &mut errors);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, ErrorKind::Io);
+        assert!(!filter.is_excluded(Path::new("/music/.hidden"), ".hidden"));
+        assert!(filter.is_included_file(Path::new("/music/notes.txt")));
+    }
+
+    #[test]
+    fn includes_modules_by_default() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join("a.mod"), b"").unwrap();
+        fs::write(root.path().join("b.txt"), b"").unwrap();
+        fs::write(root.path().join("c.XM"), b"").unwrap();
+
+        let (files, _) = scan(root.path(), ScanOptions::default());
+        assert_eq!(names(root.path(), &files), ["a.mod", "c.XM"]);
+    }
+
+    #[test]
+    fn orders_files_and_folders() {
+        let root = tempfile::tempdir().unwrap();
+        for folder in ["b", "d10", "d9"] {
+            fs::create_dir(root.path().join(folder)).unwrap();
+            fs::write(root.path().join(folder).join("x.mod"), b"").unwrap();
+        }
+        for file in ["a.mod", "c10.mod", "C2.mod"] {
+            fs::write(root.path().join(file), b"").unwrap();
+        }
+
+        let cases = [
+            (
+                ScanOrder::Interleaved,
+                [
+                    "a.mod",
+                    "b/x.mod",
+                    "C2.mod",
+                    "c10.mod",
+                    "d9/x.mod",
+                    "d10/x.mod",
+                ],
+            ),
+            (
+                ScanOrder::FoldersFirst,
+                [
+                    "b/x.mod",
+                    "d9/x.mod",
+                    "d10/x.mod",
+                    "a.mod",
+                    "C2.mod",
+                    "c10.mod",
+                ],
+            ),
+            (
+                ScanOrder::FilesFirst,
+                [
+                    "a.mod",
+                    "C2.mod",
+                    "c10.mod",
+                    "b/x.mod",
+                    "d9/x.mod",
+                    "d10/x.mod",
+                ],
+            ),
+        ];
+        for (order, expected) in cases {
+            let options = ScanOptions {
+                order,
+                ..ScanOptions::default()
+            };
+            let (files, _) = scan(root.path(), options);
+            assert_eq!(names(root.path(), &files), expected, "{:?}", order);
+        }
+    }
+}
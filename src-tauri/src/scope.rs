@@ -0,0 +1,315 @@
+use crate::events::{clamp_frame_rate, Broadcast};
+use crate::openmpt;
+use crossbeam::channel::{unbounded, Receiver, Sender, TryRecvError};
+use ringbuf::traits::{Consumer, Observer, Producer, Split};
+use ringbuf::{HeapCons, HeapProd, HeapRb};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Capacity of the sample ring buffer in interleaved samples
+const RING_CAPACITY: usize = 32_768;
+/// Length of the audio shown in one scope frame
+const WINDOW_SECONDS: f64 = 0.05;
+/// Channel scopes are rendered at a low rate, as they are only displayed
+const CHANNEL_SCOPE_RATE: i32 = 12_000;
+const MAX_CHANNEL_SCOPES: usize = 32;
+/// Channel scopes are resynchronized, if they drift further apart from the
+/// played back audio
+const MAX_DRIFT_SECONDS: f64 = 0.1;
+
+const MIN_POINTS: usize = 16;
+const MAX_POINTS: usize = 1024;
+
+#[derive(Eq, PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ScopeSettings {
+    /// Frames published per second
+    pub rate: u32,
+    /// Number of points per waveform
+    pub points: usize,
+    /// Additionally render one waveform for every channel of the module
+    pub channel_scopes: bool,
+}
+
+impl Default for ScopeSettings {
+    fn default() -> Self {
+        Self {
+            rate: 30,
+            points: 256,
+            channel_scopes: false,
+        }
+    }
+}
+
+impl ScopeSettings {
+    fn clamped(self) -> Self {
+        Self {
+            rate: clamp_frame_rate(self.rate),
+            points: self.points.clamp(MIN_POINTS, MAX_POINTS),
+            channel_scopes: self.channel_scopes,
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScopeFrame {
+    pub left: Vec<f32>,
+    pub right: Vec<f32>,
+    /// One mono waveform per module channel, if channel scopes are enabled
+    pub channels: Vec<Vec<f32>>,
+}
+
+/// Audio thread side of the oscilloscope.
+///
+/// Samples are written into a lock-free single producer single consumer ring
+/// buffer, so pushing never blocks or allocates.
+pub struct ScopeTap {
+    active: Arc<AtomicBool>,
+    producer: HeapProd<f32>,
+    sample_rate: Arc<AtomicU32>,
+    position: Arc<AtomicU64>,
+    module_sender: Sender<Option<String>>,
+}
+
+impl ScopeTap {
+    pub fn push(&mut self, sample_rate: u32, position: f64, data: &[f32]) {
+        if !self.active.load(Ordering::Relaxed) {
+            return;
+        }
+
+        self.sample_rate.store(sample_rate, Ordering::Relaxed);
+        self.position.store(position.to_bits(), Ordering::Relaxed);
+        self.producer.push_slice(data);
+    }
+
+    /// Inform the scope about the currently played module, which is needed
+    /// for channel scopes.
+    pub fn module_changed(&self, filepath: Option<&str>) {
+        let _ = self.module_sender.send(filepath.map(str::to_string));
+    }
+}
+
+/// Pick `points` evenly spaced values from the given samples
+fn decimate(samples: &VecDeque<f32>, points: usize) -> Vec<f32> {
+    if samples.is_empty() {
+        return vec![0.0; points];
+    }
+
+    (0..points)
+        .map(|point| samples[point * samples.len() / points])
+        .collect()
+}
+
+struct ChannelScope {
+    module: openmpt::module::Module,
+    history: VecDeque<f32>,
+}
+
+struct ScopeWorker {
+    consumer: HeapCons<f32>,
+    sample_rate: Arc<AtomicU32>,
+    position: Arc<AtomicU64>,
+    module_receiver: Receiver<Option<String>>,
+    frames: Broadcast<ScopeFrame>,
+    settings: Arc<Mutex<ScopeSettings>>,
+    buffer: Vec<f32>,
+    left: VecDeque<f32>,
+    right: VecDeque<f32>,
+    filepath: Option<String>,
+    channel_scopes: Option<Vec<ChannelScope>>,
+    last_tick: Instant,
+}
+
+impl ScopeWorker {
+    fn run(mut self) {
+        loop {
+            let settings = *self.settings.lock().unwrap();
+            std::thread::sleep(Duration::from_secs_f64(1.0 / settings.rate as f64));
+
+            loop {
+                match self.module_receiver.try_recv() {
+                    Ok(filepath) => {
+                        self.filepath = filepath;
+                        self.channel_scopes = None;
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => return,
+                }
+            }
+
+            let elapsed = self.last_tick.elapsed().as_secs_f64();
+            self.last_tick = Instant::now();
+
+            if !self.drain() || !self.frames.is_active() {
+                continue;
+            }
+
+            let channels = if settings.channel_scopes {
+                self.render_channel_scopes(elapsed, settings.points)
+            } else {
+                self.channel_scopes = None;
+                vec![]
+            };
+
+            self.frames.publish(ScopeFrame {
+                left: decimate(&self.left, settings.points),
+                right: decimate(&self.right, settings.points),
+                channels,
+            });
+        }
+    }
+
+    /// Move all new samples out of the ring buffer and keep the most recent
+    /// window. Returns false if there has been no new data.
+    fn drain(&mut self) -> bool {
+        let available = self.consumer.occupied_len();
+        if available == 0 {
+            return false;
+        }
+
+        self.buffer.resize(available, 0.0);
+        let count = self.consumer.pop_slice(&mut self.buffer);
+        for frame in self.buffer[..count].chunks_exact(2) {
+            self.left.push_back(frame[0]);
+            self.right.push_back(frame[1]);
+        }
+
+        let window_frames =
+            (self.sample_rate.load(Ordering::Relaxed) as f64 * WINDOW_SECONDS) as usize;
+        while self.left.len() > window_frames {
+            self.left.pop_front();
+            self.right.pop_front();
+        }
+
+        true
+    }
+
+    fn load_channel_scopes(&self) -> Vec<ChannelScope> {
+        let Some(ref filepath) = self.filepath else {
+            return vec![];
+        };
+        let Ok(data) = std::fs::read(filepath) else {
+            return vec![];
+        };
+
+        let channel_count = match openmpt::module::Module::try_from_memory(&data) {
+            Ok(module) => (module.get_num_channels().max(0) as usize).min(MAX_CHANNEL_SCOPES),
+            Err(_) => return vec![],
+        };
+
+        let mut scopes = vec![];
+        for channel in 0..channel_count as i32 {
+            let Ok(mut module) = openmpt::module::Module::try_from_memory_interactive(&data) else {
+                return vec![];
+            };
+
+            let muted = (0..module.get_num_channels())
+                .filter(|&other| other != channel)
+                .try_for_each(|other| module.set_channel_mute(other, true));
+            if let Err(err) = muted {
+                eprintln!("Channel scopes are not available for {}: {}", filepath, err);
+                return vec![];
+            }
+
+            scopes.push(ChannelScope {
+                module,
+                history: VecDeque::new(),
+            });
+        }
+
+        scopes
+    }
+
+    fn render_channel_scopes(&mut self, elapsed: f64, points: usize) -> Vec<Vec<f32>> {
+        if self.channel_scopes.is_none() {
+            self.channel_scopes = Some(self.load_channel_scopes());
+        }
+
+        let position = f64::from_bits(self.position.load(Ordering::Relaxed));
+        let frames = (elapsed * CHANNEL_SCOPE_RATE as f64) as usize;
+        let window_frames = (CHANNEL_SCOPE_RATE as f64 * WINDOW_SECONDS) as usize;
+        let mut buffer = vec![0f32; frames.min(CHANNEL_SCOPE_RATE as usize)];
+
+        let scopes = self.channel_scopes.as_mut().unwrap();
+        scopes
+            .iter_mut()
+            .map(|scope| {
+                if (scope.module.get_position_seconds() - position).abs() > MAX_DRIFT_SECONDS {
+                    scope.module.set_position_seconds(position);
+                }
+
+                let count = scope.module.read_mono(CHANNEL_SCOPE_RATE, &mut buffer);
+                scope.history.extend(&buffer[..count]);
+                while scope.history.len() > window_frames {
+                    scope.history.pop_front();
+                }
+
+                decimate(&scope.history, points)
+            })
+            .collect()
+    }
+}
+
+/// Tracker style oscilloscope, publishing decimated waveforms of the played
+/// audio to subscribed channels.
+pub struct Oscilloscope {
+    frames: Broadcast<ScopeFrame>,
+    settings: Arc<Mutex<ScopeSettings>>,
+}
+
+impl Oscilloscope {
+    pub fn spawn(settings: ScopeSettings) -> (Self, ScopeTap) {
+        let (producer, consumer) = HeapRb::<f32>::new(RING_CAPACITY).split();
+        let (module_sender, module_receiver) = unbounded();
+        let frames = Broadcast::new("scope");
+        let sample_rate = Arc::new(AtomicU32::new(48_000));
+        let position = Arc::new(AtomicU64::new(0f64.to_bits()));
+        let settings = Arc::new(Mutex::new(settings.clamped()));
+
+        let worker = ScopeWorker {
+            consumer,
+            sample_rate: sample_rate.clone(),
+            position: position.clone(),
+            module_receiver,
+            frames: frames.clone(),
+            settings: settings.clone(),
+            buffer: vec![],
+            left: VecDeque::new(),
+            right: VecDeque::new(),
+            filepath: None,
+            channel_scopes: None,
+            last_tick: Instant::now(),
+        };
+        std::thread::spawn(move || worker.run());
+
+        let tap = ScopeTap {
+            active: frames.active(),
+            producer,
+            sample_rate,
+            position,
+            module_sender,
+        };
+
+        (Self { frames, settings }, tap)
+    }
+
+    /// Change rate, resolution and channel scopes of the published frames.
+    /// Out of range values are clamped, the effective settings are returned.
+    pub fn configure(&self, settings: ScopeSettings) -> ScopeSettings {
+        let settings = settings.clamped();
+        *self.settings.lock().unwrap() = settings;
+        settings
+    }
+
+    pub fn subscribe(&self, channel: tauri::ipc::Channel<ScopeFrame>) -> String {
+        self.frames.subscribe(channel)
+    }
+
+    pub fn unsubscribe(&self, uuid: String) -> bool {
+        self.frames.unsubscribe(uuid)
+    }
+}
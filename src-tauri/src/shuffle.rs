@@ -0,0 +1,371 @@
+use rand::distributions::WeightedIndex;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::Path;
+
+/// Rating of unrated entries, in the middle of the range from 1 to 5, so
+/// they are neither favoured nor avoided
+const UNRATED: u8 = 3;
+
+#[derive(Eq, PartialEq, Copy, Clone, Default, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ShuffleMode {
+    /// Play the playlist in order
+    #[default]
+    Off,
+    /// Play every entry once in random order
+    Random,
+    /// Play the folders in random order, and the files of every folder in
+    /// playlist order
+    Folder,
+    /// Random order, which favours highly rated entries and entries, which
+    /// have been played less often. Entries may repeat, but a round ends after as many entries as
+    /// the playlist has playable ones.
+    Weighted,
+}
+
+/// Decides, which playlist entry is played next.
+///
+/// Entries are tracked by their filepath, so the state survives changes of
+/// the playlist order.
+pub struct Shuffler {
+    mode: ShuffleMode,
+    rng: StdRng,
+    /// Entries played since the playlist has been (re)started
+    played: HashSet<String>,
+    /// Number of entries played since the playlist has been (re)started,
+    /// including repetitions
+    round_length: usize,
+    play_counts: HashMap<String, u32>,
+    /// Ratings from the library. They don't depend on the playlist, so they
+    /// survive `reset`.
+    ratings: HashMap<String, u8>,
+    /// Entries, which could not be opened. Weighted shuffling would pick
+    /// them again otherwise.
+    unplayable: HashSet<String>,
+    /// Entries to play next, before picking randomly again
+    queued: VecDeque<String>,
+    /// The entry picked by `peek` for the entry at the first index, so the
+    /// preloader and `advance` agree on it
+    upcoming: Option<(usize, usize)>,
+}
+
+impl Shuffler {
+    pub fn new(mode: ShuffleMode) -> Self {
+        Self {
+            mode,
+            rng: StdRng::from_entropy(),
+            played: HashSet::new(),
+            round_length: 0,
+            play_counts: HashMap::new(),
+            ratings: HashMap::new(),
+            unplayable: HashSet::new(),
+            queued: VecDeque::new(),
+            upcoming: None,
+        }
+    }
+
+    pub fn mode(&self) -> ShuffleMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: ShuffleMode) {
+        self.mode = mode;
+        self.upcoming = None;
+    }
+
+    /// Forget which entries have been played, e.g. after loading a new
+    /// playlist.
+    pub fn reset(&mut self) {
+        self.played.clear();
+        self.round_length = 0;
+        self.play_counts.clear();
+        self.unplayable.clear();
+        self.queued.clear();
+        self.upcoming = None;
+    }
+
+    /// Start another round through the same playlist. Play counts are kept,
+    /// so weighted shuffling still favours the less played entries.
+    pub fn restart(&mut self) {
+        self.played.clear();
+        self.round_length = 0;
+        self.upcoming = None;
+    }
+
+    /// Pick the upcoming entry again, because the playlist has been edited
+    pub fn forget_upcoming(&mut self) {
+        self.upcoming = None;
+    }
+
+    /// Play the given entries right after the current one. In playlist order
+    /// they are simply inserted there, but shuffling has to know about them.
+    pub fn queue_next(&mut self, files: &[String]) {
+        for filepath in files.iter().rev() {
+            self.queued.push_front(filepath.clone());
+        }
+        self.upcoming = None;
+    }
+
+    pub fn set_ratings(&mut self, ratings: HashMap<String, u8>) {
+        self.ratings = ratings;
+    }
+
+    /// Change the rating of an entry, `None` makes it unrated
+    pub fn set_rating(&mut self, filepath: &str, rating: Option<u8>) {
+        match rating {
+            Some(rating) => self.ratings.insert(filepath.to_string(), rating),
+            None => self.ratings.remove(filepath),
+        };
+    }
+
+    pub fn mark_played(&mut self, filepath: &str) {
+        self.queued.retain(|queued| queued != filepath);
+        self.played.insert(filepath.to_string());
+        self.round_length += 1;
+        *self.play_counts.entry(filepath.to_string()).or_default() += 1;
+    }
+
+    pub fn mark_unplayable(&mut self, filepath: &str) {
+        self.queued.retain(|queued| queued != filepath);
+        self.unplayable.insert(filepath.to_string());
+    }
+
+    /// Index of the entry to start a playlist with
+    pub fn first(&mut self, files: &[String]) -> usize {
+        let first = match self.mode {
+            ShuffleMode::Off => None,
+            ShuffleMode::Random => self.pick_unplayed(files, None),
+            ShuffleMode::Weighted => self.pick_weighted(files, None),
+            ShuffleMode::Folder => self.pick_folder(files, None),
+        };
+        let first = first.unwrap_or(0);
+        if let Some(filepath) = files.get(first) {
+            self.mark_played(filepath);
+        }
+        first
+    }
+
+    /// The entry, which will be played after `current`, without advancing
+    pub fn peek(&mut self, files: &[String], current: usize) -> Option<usize> {
+        match self.upcoming {
+            Some((from, next)) if from == current && next < files.len() => Some(next),
+            _ => {
+                let next = self.pick(files, current);
+                self.upcoming = next.map(|next| (current, next));
+                next
+            }
+        }
+    }
+
+    /// Pick the entry, which is played after `current` and mark it as played
+    pub fn advance(&mut self, files: &[String], current: usize) -> Option<usize> {
+        let next = self.peek(files, current);
+        self.upcoming = None;
+        if let Some(index) = next {
+            self.mark_played(&files[index]);
+        }
+        next
+    }
+
+    fn pick(&mut self, files: &[String], current: usize) -> Option<usize> {
+        if self.mode != ShuffleMode::Off {
+            if let Some(index) = self.pick_queued(files) {
+                return Some(index);
+            }
+        }
+
+        match self.mode {
+            ShuffleMode::Off => (current + 1 < files.len()).then_some(current + 1),
+            ShuffleMode::Random => self.pick_unplayed(files, Some(current)),
+            ShuffleMode::Folder => self.pick_in_folder(files, current),
+            ShuffleMode::Weighted => self.pick_weighted(files, Some(current)),
+        }
+    }
+
+    /// The first queued entry, which is still part of the playlist
+    fn pick_queued(&mut self, files: &[String]) -> Option<usize> {
+        while let Some(filepath) = self.queued.front() {
+            if let Some(index) = files.iter().position(|file| file == filepath) {
+                return Some(index);
+            }
+            self.queued.pop_front();
+        }
+        None
+    }
+
+    fn pick_unplayed(&mut self, files: &[String], current: Option<usize>) -> Option<usize> {
+        let candidates: Vec<usize> = (0..files.len())
+            .filter(|index| Some(*index) != current && !self.played.contains(&files[*index]))
+            .collect();
+        candidates.choose(&mut self.rng).copied()
+    }
+
+    /// Continue with the next unplayed file of the current folder, or start
+    /// a random unplayed folder.
+    fn pick_in_folder(&mut self, files: &[String], current: usize) -> Option<usize> {
+        let folder = files.get(current).and_then(|filepath| folder_of(filepath));
+        let next_in_folder = (current + 1..files.len()).find(|index| {
+            folder_of(&files[*index]) == folder && !self.played.contains(&files[*index])
+        });
+
+        next_in_folder.or_else(|| self.pick_folder(files, Some(current)))
+    }
+
+    /// First unplayed file of a random folder
+    fn pick_folder(&mut self, files: &[String], current: Option<usize>) -> Option<usize> {
+        let mut folders: Vec<&Path> = vec![];
+        let mut firsts: Vec<usize> = vec![];
+        for (index, filepath) in files.iter().enumerate() {
+            if Some(index) == current || self.played.contains(filepath) {
+                continue;
+            }
+            let folder = folder_of(filepath).unwrap_or(Path::new(""));
+            if !folders.contains(&folder) {
+                folders.push(folder);
+                firsts.push(index);
+            }
+        }
+
+        firsts.choose(&mut self.rng).copied()
+    }
+
+    /// Entries are weighted by their rating and by how often they have been
+    /// played. Every star above or below an average rating doubles or halves
+    /// the weight, so unplayed and highly rated entries are the most favoured
+    /// ones.
+    ///
+    /// Without an end the playlist would never finish unless repeat is
+    /// enabled, so the round is over once it has been as long as a pass
+    /// through all playable entries.
+    fn pick_weighted(&mut self, files: &[String], current: Option<usize>) -> Option<usize> {
+        let playable = files
+            .iter()
+            .filter(|filepath| !self.unplayable.contains(*filepath))
+            .count();
+        if self.round_length >= playable {
+            return None;
+        }
+
+        let candidates: Vec<usize> = (0..files.len())
+            .filter(|index| Some(*index) != current && !self.unplayable.contains(&files[*index]))
+            .collect();
+        let weights = candidates.iter().map(|index| {
+            let count = self.play_counts.get(&files[*index]).copied().unwrap_or(0);
+            let rating = self.ratings.get(&files[*index]).copied().unwrap_or(UNRATED);
+            2f64.powi(rating as i32 - UNRATED as i32) / (1.0 + count as f64).powi(2)
+        });
+        let distribution = WeightedIndex::new(weights).ok()?;
+        Some(candidates[self.rng.sample(distribution)])
+    }
+}
+
+fn folder_of(filepath: &str) -> Option<&Path> {
+    Path::new(filepath).parent()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ShuffleMode, Shuffler};
+    use std::collections::HashMap;
+
+    fn files(count: usize) -> Vec<String> {
+        (0..count)
+            .map(|index| format!("/music/{}.mod", index))
+            .collect()
+    }
+
+    /// Play through the playlist until the shuffler ends the round
+    fn round(shuffler: &mut Shuffler, files: &[String]) -> Vec<usize> {
+        let mut order = vec![shuffler.first(files)];
+        while let Some(next) = shuffler.advance(files, *order.last().unwrap()) {
+            order.push(next);
+            assert!(order.len() <= files.len(), "round never ends");
+        }
+        order
+    }
+
+    #[test]
+    fn random_plays_every_entry_once() {
+        let files = files(20);
+        let mut shuffler = Shuffler::new(ShuffleMode::Random);
+        let mut order = round(&mut shuffler, &files);
+        order.sort();
+        assert_eq!(order, (0..20).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn queued_entries_play_next() {
+        let files = files(20);
+        for mode in [
+            ShuffleMode::Random,
+            ShuffleMode::Folder,
+            ShuffleMode::Weighted,
+        ] {
+            let mut shuffler = Shuffler::new(mode);
+            let current = shuffler.first(&files);
+            let queued: Vec<String> = [7, 3]
+                .into_iter()
+                .filter(|index| *index != current)
+                .map(|index| files[index].clone())
+                .collect();
+            shuffler.queue_next(&queued);
+
+            let mut index = current;
+            for filepath in queued.iter() {
+                // The preloader sees the queued entry as well
+                let upcoming = shuffler.peek(&files, index);
+                index = shuffler.advance(&files, index).unwrap();
+                assert_eq!(upcoming, Some(index));
+                assert_eq!(&files[index], filepath, "{:?}", mode);
+            }
+        }
+    }
+
+    #[test]
+    fn weighted_ends_after_one_pass() {
+        let files = files(20);
+        let mut shuffler = Shuffler::new(ShuffleMode::Weighted);
+        assert_eq!(round(&mut shuffler, &files).len(), 20);
+
+        shuffler.restart();
+        assert_eq!(round(&mut shuffler, &files).len(), 20);
+    }
+
+    #[test]
+    fn weighted_pass_skips_unplayable_entries() {
+        let files = files(5);
+        let mut shuffler = Shuffler::new(ShuffleMode::Weighted);
+        shuffler.mark_unplayable(&files[3]);
+        shuffler.mark_unplayable(&files[4]);
+
+        let order = round(&mut shuffler, &files);
+        assert_eq!(order.len(), 3);
+        assert!(order.iter().all(|index| *index < 3));
+    }
+
+    #[test]
+    fn weighted_favours_highly_rated_entries() {
+        let files = files(3);
+        let ratings = HashMap::from([
+            (files[0].clone(), 5),
+            (files[1].clone(), 1),
+            (files[2].clone(), 1),
+        ]);
+
+        // Entry 0 weighs 4, entry 1 only 0.25, so it is picked in 94% of all
+        // cases
+        let mut favoured = 0;
+        for _ in 0..1000 {
+            let mut shuffler = Shuffler::new(ShuffleMode::Weighted);
+            shuffler.set_ratings(ratings.clone());
+            if shuffler.advance(&files, 2) == Some(0) {
+                favoured += 1;
+            }
+        }
+        assert!(favoured > 850, "picked {} times", favoured);
+    }
+}
@@ -0,0 +1,223 @@
+use crate::events::{clamp_frame_rate, Broadcast};
+use crossbeam::channel::{bounded, Receiver, Sender};
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+const FFT_SIZE: usize = 2048;
+const MIN_FREQUENCY: f32 = 40.0;
+const MAX_FREQUENCY: f32 = 16_000.0;
+/// Level mapped to an empty bar
+const FLOOR_DB: f32 = -72.0;
+/// Number of sample buffers, which may be in flight between the audio thread
+/// and the analyzer
+const BUFFER_COUNT: usize = 8;
+
+const MIN_BANDS: usize = 4;
+const MAX_BANDS: usize = 128;
+
+#[derive(Eq, PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SpectrumSettings {
+    /// Frames published per second
+    pub rate: u32,
+    pub bands: usize,
+}
+
+impl Default for SpectrumSettings {
+    fn default() -> Self {
+        Self {
+            rate: 30,
+            bands: 16,
+        }
+    }
+}
+
+impl SpectrumSettings {
+    fn clamped(self) -> Self {
+        Self {
+            rate: clamp_frame_rate(self.rate),
+            bands: self.bands.clamp(MIN_BANDS, MAX_BANDS),
+        }
+    }
+}
+
+/// Band magnitudes from low to high frequencies, normalized to 0.0..=1.0
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpectrumFrame {
+    pub bands: Vec<f32>,
+}
+
+struct SamplesBuffer {
+    sample_rate: u32,
+    samples: Vec<f32>,
+}
+
+/// Audio thread side of the spectrum analyzer.
+///
+/// Copies rendered samples into recycled buffers and hands them over without
+/// ever blocking. If the analyzer can't keep up, data is simply dropped.
+pub struct SpectrumTap {
+    active: Arc<AtomicBool>,
+    sender: Sender<SamplesBuffer>,
+    recycle: Receiver<Vec<f32>>,
+}
+
+impl SpectrumTap {
+    pub fn push(&self, sample_rate: u32, data: &[f32]) {
+        if !self.active.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let mut samples = self.recycle.try_recv().unwrap_or_default();
+        samples.clear();
+        samples.extend_from_slice(data);
+        let _ = self.sender.try_send(SamplesBuffer {
+            sample_rate,
+            samples,
+        });
+    }
+}
+
+struct AnalyzerWorker {
+    receiver: Receiver<SamplesBuffer>,
+    recycle: Sender<Vec<f32>>,
+    frames: Broadcast<SpectrumFrame>,
+    settings: Arc<Mutex<SpectrumSettings>>,
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    history: VecDeque<f32>,
+    samples_since_frame: usize,
+}
+
+impl AnalyzerWorker {
+    fn run(mut self) {
+        while let Ok(buffer) = self.receiver.recv() {
+            let settings = *self.settings.lock().unwrap();
+            let samples_per_frame = buffer.sample_rate as usize / settings.rate as usize;
+
+            for frame in buffer.samples.chunks_exact(2) {
+                if self.history.len() == FFT_SIZE {
+                    self.history.pop_front();
+                }
+                self.history.push_back((frame[0] + frame[1]) / 2.0);
+
+                self.samples_since_frame += 1;
+                if self.samples_since_frame >= samples_per_frame {
+                    self.samples_since_frame = 0;
+                    let frame = self.analyze(buffer.sample_rate, settings.bands);
+                    self.frames.publish(frame);
+                }
+            }
+
+            let _ = self.recycle.try_send(buffer.samples);
+        }
+    }
+
+    fn analyze(&self, sample_rate: u32, band_count: usize) -> SpectrumFrame {
+        let mut spectrum: Vec<Complex<f32>> = self
+            .history
+            .iter()
+            .zip(self.window.iter())
+            .map(|(sample, window)| Complex::new(sample * window, 0.0))
+            .collect();
+        spectrum.resize(FFT_SIZE, Complex::default());
+        self.fft.process(&mut spectrum);
+
+        let window_gain: f32 = self.window.iter().sum::<f32>() / 2.0;
+        let bin_width = sample_rate as f32 / FFT_SIZE as f32;
+        let max_frequency = MAX_FREQUENCY.min(sample_rate as f32 / 2.0);
+        let ratio = (max_frequency / MIN_FREQUENCY).powf(1.0 / band_count as f32);
+
+        let bands = (0..band_count)
+            .map(|band| {
+                let low = MIN_FREQUENCY * ratio.powi(band as i32);
+                let high = low * ratio;
+                let first = ((low / bin_width).round() as usize).clamp(1, FFT_SIZE / 2 - 1);
+                let last = ((high / bin_width).round() as usize).clamp(first, FFT_SIZE / 2 - 1);
+
+                let magnitude = spectrum[first..=last]
+                    .iter()
+                    .map(|bin| bin.norm())
+                    .fold(0.0, f32::max)
+                    / window_gain;
+                let db = 20.0 * magnitude.max(1e-9).log10();
+                ((db - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0)
+            })
+            .collect();
+
+        SpectrumFrame { bands }
+    }
+}
+
+/// FFT based spectrum analyzer, publishing band magnitudes of the played
+/// audio to subscribed channels.
+pub struct SpectrumAnalyzer {
+    sender: Sender<SamplesBuffer>,
+    recycle: Receiver<Vec<f32>>,
+    frames: Broadcast<SpectrumFrame>,
+    settings: Arc<Mutex<SpectrumSettings>>,
+}
+
+impl SpectrumAnalyzer {
+    pub fn spawn(settings: SpectrumSettings) -> Self {
+        let settings = settings.clamped();
+        let (sender, receiver) = bounded(BUFFER_COUNT);
+        let (recycle_sender, recycle) = bounded(BUFFER_COUNT);
+        let frames = Broadcast::new("spectrum");
+        let settings = Arc::new(Mutex::new(settings));
+
+        // Hann window
+        let window = (0..FFT_SIZE)
+            .map(|n| 0.5 - 0.5 * (2.0 * PI * n as f32 / (FFT_SIZE - 1) as f32).cos())
+            .collect();
+
+        let worker = AnalyzerWorker {
+            receiver,
+            recycle: recycle_sender,
+            frames: frames.clone(),
+            settings: settings.clone(),
+            fft: FftPlanner::new().plan_fft_forward(FFT_SIZE),
+            window,
+            history: VecDeque::with_capacity(FFT_SIZE),
+            samples_since_frame: 0,
+        };
+        std::thread::spawn(move || worker.run());
+
+        Self {
+            sender,
+            recycle,
+            frames,
+            settings,
+        }
+    }
+
+    pub fn tap(&self) -> SpectrumTap {
+        SpectrumTap {
+            active: self.frames.active(),
+            sender: self.sender.clone(),
+            recycle: self.recycle.clone(),
+        }
+    }
+
+    /// Change rate and band count of the published frames. Returns the
+    /// settings actually in effect.
+    pub fn configure(&self, settings: SpectrumSettings) -> SpectrumSettings {
+        let settings = settings.clamped();
+        *self.settings.lock().unwrap() = settings;
+        settings
+    }
+
+    pub fn subscribe(&self, channel: tauri::ipc::Channel<SpectrumFrame>) -> String {
+        self.frames.subscribe(channel)
+    }
+
+    pub fn unsubscribe(&self, uuid: String) -> bool {
+        self.frames.unsubscribe(uuid)
+    }
+}
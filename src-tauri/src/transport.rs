@@ -0,0 +1,174 @@
+use serde::{Deserialize, Serialize};
+
+/// Transport state of the player.
+///
+/// Valid transitions are:
+/// - from any state to `Loading`, whenever a module is about to be opened
+/// - from any state to `Idle`, when the playlist is empty or has been cleared
+/// - from `Loading` to `Playing`, to `Ended` if nothing playable is left, or
+///   to `Stopped` when recovering from a crash
+/// - from `Playing` to `Paused`, `Stopped` or `Ended`
+/// - from `Paused` to `Playing` or `Stopped`, or to `Ended` if the last entry
+///   has been removed
+#[derive(Serialize, Copy, Clone, Eq, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum TransportState {
+    /// Nothing has been loaded yet or the playlist is empty
+    Idle,
+    Loading,
+    Playing,
+    Paused,
+    /// Playback has been stopped and the module unloaded
+    Stopped,
+    /// The end of the playlist has been reached
+    Ended,
+}
+
+impl TransportState {
+    pub fn can_transition_to(self, next: TransportState) -> bool {
+        use TransportState::*;
+
+        matches!(
+            (self, next),
+            (_, Loading | Idle)
+                | (Loading, Playing | Stopped | Ended)
+                | (Playing, Paused | Stopped | Ended)
+                | (Paused, Playing | Stopped | Ended)
+        )
+    }
+
+    /// Move to the given state. Returns true, if the state has changed and
+    /// has to be announced. Invalid transitions keep the current state.
+    pub fn transition(&mut self, next: TransportState) -> bool {
+        if *self == next {
+            return false;
+        }
+
+        if !self.can_transition_to(next) {
+            eprintln!("Ignoring invalid transition from {:?} to {:?}", self, next);
+            return false;
+        }
+
+        *self = next;
+        true
+    }
+}
+
+/// What happens, when a module has been played until its end
+#[derive(Eq, PartialEq, Copy, Clone, Default, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RepeatMode {
+    /// Continue with the next module and end with the playlist
+    #[default]
+    Off,
+    /// Start over, once the end of the playlist has been reached
+    All,
+    /// Play the current module again
+    One,
+    /// Stop after every module
+    StopAfterCurrent,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TransportState::{self, *};
+
+    const ALL: [TransportState; 6] = [Idle, Loading, Playing, Paused, Stopped, Ended];
+
+    /// Apply the transitions of a player action and collect the announced
+    /// states
+    fn run(state: &mut TransportState, transitions: &[TransportState]) -> Vec<TransportState> {
+        transitions
+            .iter()
+            .filter(|next| state.transition(**next))
+            .copied()
+            .collect()
+    }
+
+    #[test]
+    fn legal_transitions() {
+        for from in ALL {
+            assert!(from.can_transition_to(Loading));
+            assert!(from.can_transition_to(Idle));
+        }
+        for (from, to) in [
+            (Loading, Playing),
+            (Loading, Stopped),
+            (Loading, Ended),
+            (Playing, Paused),
+            (Playing, Stopped),
+            (Playing, Ended),
+            (Paused, Playing),
+            (Paused, Stopped),
+            (Paused, Ended),
+        ] {
+            assert!(from.can_transition_to(to), "{:?} -> {:?}", from, to);
+        }
+    }
+
+    #[test]
+    fn illegal_transitions() {
+        for (from, to) in [
+            (Idle, Playing),
+            (Idle, Paused),
+            (Idle, Stopped),
+            (Idle, Ended),
+            (Loading, Paused),
+            (Stopped, Playing),
+            (Stopped, Paused),
+            (Stopped, Ended),
+            (Ended, Playing),
+            (Ended, Paused),
+            (Ended, Stopped),
+            (Playing, Playing),
+            (Paused, Paused),
+        ] {
+            assert!(!from.can_transition_to(to), "{:?} -> {:?}", from, to);
+        }
+    }
+
+    #[test]
+    fn illegal_transition_keeps_state() {
+        let mut state = Stopped;
+        assert!(!state.transition(Paused));
+        assert_eq!(state, Stopped);
+    }
+
+    #[test]
+    fn repeated_state_is_not_announced() {
+        let mut state = Loading;
+        assert!(!state.transition(Loading));
+        assert_eq!(state, Loading);
+    }
+
+    #[test]
+    fn play_pause_stop() {
+        let mut state = Idle;
+        assert_eq!(run(&mut state, &[Loading, Playing]), [Loading, Playing]);
+        assert_eq!(run(&mut state, &[Paused]), [Paused]);
+        // Pausing twice doesn't announce anything
+        assert_eq!(run(&mut state, &[Paused]), []);
+        assert_eq!(run(&mut state, &[Playing]), [Playing]);
+        assert_eq!(run(&mut state, &[Stopped]), [Stopped]);
+        // Pausing a stopped player is ignored
+        assert_eq!(run(&mut state, &[Paused]), []);
+        assert_eq!(state, Stopped);
+    }
+
+    #[test]
+    fn next_and_end() {
+        let mut state = Playing;
+        assert_eq!(run(&mut state, &[Loading, Playing]), [Loading, Playing]);
+        // Nothing playable is left at the end of the playlist
+        assert_eq!(run(&mut state, &[Loading, Ended]), [Loading, Ended]);
+        // Playing again starts over
+        assert_eq!(run(&mut state, &[Loading, Playing]), [Loading, Playing]);
+    }
+
+    #[test]
+    fn clear_while_playing() {
+        let mut state = Paused;
+        assert_eq!(run(&mut state, &[Idle]), [Idle]);
+        assert_eq!(run(&mut state, &[Idle]), []);
+    }
+}